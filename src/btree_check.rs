@@ -0,0 +1,62 @@
+mod btree_node;
+
+use crate::btree_node::BTree;
+use std::env;
+use std::path::Path;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() < 3 {
+        eprintln!("usage: btree_check <check|repair> <index-file> [repair-output-file]");
+        process::exit(2);
+    }
+
+    let mode = args[1].as_str();
+    let index_path = Path::new(&args[2]);
+
+    let mut bt = BTree::open(index_path);
+
+    match mode {
+        "check" => {
+            let report = bt.check();
+            print_report(&report);
+            if !report.is_clean() {
+                process::exit(1);
+            }
+        }
+        "repair" => {
+            if args.len() < 4 {
+                eprintln!("usage: btree_check repair <index-file> <repair-output-file>");
+                process::exit(2);
+            }
+            let dest_path = Path::new(&args[3]);
+
+            let before = bt.check();
+            println!("Before repair:");
+            print_report(&before);
+
+            let mut rebuilt = bt.repair(dest_path).expect("repair failed");
+            let after = rebuilt.check();
+            println!("\nAfter repair ({}):", dest_path.display());
+            print_report(&after);
+        }
+        other => {
+            eprintln!("unknown mode '{}', expected 'check' or 'repair'", other);
+            process::exit(2);
+        }
+    }
+}
+
+fn print_report(report: &btree_node::CheckReport) {
+    println!("nodes       : {}", report.nodes);
+    println!("leaves      : {}", report.leaves);
+    println!("height      : {}", report.height);
+    println!("total keys  : {}", report.total_keys);
+    println!("orphan pages: {:?}", report.orphaned_pages);
+    println!("violations  : {}", report.violations.len());
+    for v in &report.violations {
+        println!("  - page {}: {}", v.page_id, v.message);
+    }
+}
@@ -1,21 +1,261 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::convert::TryInto;
 use std::fs::File;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{self, Read, Seek, SeekFrom};
 use std::num::NonZeroUsize;
-use std::path::Path;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 use lru::LruCache;
+use memmap2::Mmap;
 
 pub const PAGE_SIZE: usize = 8192;
 pub const ORDER: usize = 100;
 pub const MAX_KEYS: usize = 2 * ORDER - 1;
 pub static DISK_READS: AtomicU64 = AtomicU64::new(0);
 pub static DISK_WRITES: AtomicU64 = AtomicU64::new(0);
+/// Pages served directly out of the mmap path in `BufferPool::read_page_copy`,
+/// bypassing both the LRU cache and `DISK_READS` entirely. Distinct from
+/// `DISK_READS` because an mmap hit may still fault a page in from disk under
+/// the hood, but that cost is the kernel's page cache, not ours to count.
+pub static MMAP_HITS: AtomicU64 = AtomicU64::new(0);
+
+/// Bytes reserved at the start of every page for a CRC32 checksum of the rest
+/// of the page. Applies to the header page (page 0) as well as node pages.
+pub const CHECKSUM_SIZE: usize = 4;
+
+/// Encoded size of a `RecordPointer` in format versions before 2: an 8-byte
+/// offset, a 4-byte length, and an 8-byte frame id (`u64::MAX` standing in
+/// for `None`). No `file_id`; those pointers implicitly meant segment 0.
+const RECORD_POINTER_SIZE_V1: usize = 20;
+
+/// Encoded size of a `RecordPointer` from format version 2 on: `V1` plus a
+/// trailing 4-byte `file_id`.
+const RECORD_POINTER_SIZE_V2: usize = 24;
+
+/// Format version `RecordPointer::file_id` was introduced in; node pages
+/// written by an older version don't reserve the trailing 4 bytes.
+const FILE_ID_FORMAT_VERSION: u8 = 2;
+
+/// On-disk size of a `RecordPointer` for a page encoded at `format_version`.
+fn record_pointer_size(format_version: u8) -> usize {
+    if format_version >= FILE_ID_FORMAT_VERSION {
+        RECORD_POINTER_SIZE_V2
+    } else {
+        RECORD_POINTER_SIZE_V1
+    }
+}
+
+/// Sentinel `frame_id` on disk for `RecordPointer::frame_id == None`.
+const NO_FRAME_ID: u64 = u64::MAX;
+
+/// Format version key lengths, `RecordPointer::offset`/`length`, and child
+/// page numbers switched from fixed-width little-endian ints to LEB128
+/// varints. `frame_id`/`file_id` stay fixed-width either way: they're
+/// already compact (a sentinel-or-small-int, a small segment id) and aren't
+/// worth the extra per-field branch.
+const VARINT_FORMAT_VERSION: u8 = 3;
+
+/// Append `value` to `buf` at `pos` as a LEB128 varint (7 bits per byte,
+/// little-endian, high bit set on every byte but the last) and return the
+/// position right after it.
+fn write_varint(buf: &mut [u8; PAGE_SIZE], mut pos: usize, mut value: u64) -> usize {
+    loop {
+        let mut byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value != 0 {
+            byte |= 0x80;
+        }
+        buf[pos] = byte;
+        pos += 1;
+        if value == 0 {
+            break;
+        }
+    }
+    pos
+}
+
+/// Decode a LEB128 varint starting at `pos`, returning its value and how
+/// many bytes it took. Bounds- and length-checked since this reads untrusted
+/// on-disk bytes: a torn write could otherwise run the loop off the page or
+/// past `u64`'s width.
+fn read_varint(buf: &[u8; PAGE_SIZE], pos: usize) -> Result<(u64, usize), BTreeError> {
+    let mut value: u64 = 0;
+    let mut shift: u32 = 0;
+    let mut i = pos;
+    loop {
+        if i >= PAGE_SIZE {
+            return Err(BTreeError::Malformed(format!("varint at byte {} runs past the page", pos)));
+        }
+        if shift >= 64 {
+            return Err(BTreeError::Malformed(format!("varint at byte {} is too long", pos)));
+        }
+        let byte = buf[i];
+        i += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    Ok((value, i - pos))
+}
+
+/// Byte offset in the header page (page 0) where the segment registry's
+/// `u16` entry count begins, right after the magic/version stamp. Only
+/// meaningful from `FILE_ID_FORMAT_VERSION` on; on older pages this region is
+/// unused and reads back as an empty table.
+const SEGMENT_TABLE_OFFSET: usize = CHECKSUM_SIZE + 29;
+
+/// Decode the segment registry (`file_id -> log file path`) from a header
+/// page. Tolerant of a truncated/corrupt table: stops and returns whatever
+/// was decoded so far rather than panicking, since it's read on every `open`.
+fn read_segment_table(header: &[u8; PAGE_SIZE]) -> HashMap<u32, PathBuf> {
+    let mut segments = HashMap::new();
+    let mut pos = SEGMENT_TABLE_OFFSET;
+    if pos + 2 > PAGE_SIZE {
+        return segments;
+    }
+    let count = u16::from_le_bytes(header[pos..pos + 2].try_into().unwrap());
+    pos += 2;
+    for _ in 0..count {
+        if pos + 6 > PAGE_SIZE {
+            break;
+        }
+        let file_id = u32::from_le_bytes(header[pos..pos + 4].try_into().unwrap());
+        pos += 4;
+        let path_len = u16::from_le_bytes(header[pos..pos + 2].try_into().unwrap()) as usize;
+        pos += 2;
+        if pos + path_len > PAGE_SIZE {
+            break;
+        }
+        let path = String::from_utf8_lossy(&header[pos..pos + path_len]).into_owned();
+        pos += path_len;
+        segments.insert(file_id, PathBuf::from(path));
+    }
+    segments
+}
+
+/// Encode the segment registry into a header page, failing rather than
+/// silently truncating if it no longer fits in the reserved region.
+fn write_segment_table(header: &mut [u8; PAGE_SIZE], segments: &HashMap<u32, PathBuf>) -> io::Result<()> {
+    let count_pos = SEGMENT_TABLE_OFFSET;
+    let mut pos = count_pos + 2;
+    let mut written: u16 = 0;
+    for (file_id, path) in segments {
+        let path_bytes = path.to_string_lossy();
+        let path_bytes = path_bytes.as_bytes();
+        let entry_len = 4 + 2 + path_bytes.len();
+        if pos + entry_len > PAGE_SIZE {
+            return Err(io::Error::other(format!(
+                "segment registry has no room left in the header page for file_id {}",
+                file_id
+            )));
+        }
+        header[pos..pos + 4].copy_from_slice(&file_id.to_le_bytes());
+        pos += 4;
+        header[pos..pos + 2].copy_from_slice(&(path_bytes.len() as u16).to_le_bytes());
+        pos += 2;
+        header[pos..pos + path_bytes.len()].copy_from_slice(path_bytes);
+        pos += path_bytes.len();
+        written += 1;
+    }
+    header[count_pos..count_pos + 2].copy_from_slice(&written.to_le_bytes());
+    Ok(())
+}
+
+/// Why a page or the index file could not be read back as a tree.
+#[derive(Debug)]
+pub enum BTreeError {
+    Io(std::io::Error),
+    /// A page failed its checksum check on read: the bytes on disk do not
+    /// match what was written, which means a torn write or some other silent
+    /// corruption.
+    CorruptPage { page_id: u64, expected: u32, found: u32 },
+    /// A page passed its checksum but its node layout doesn't parse: a
+    /// length field runs past the page, or key bytes aren't valid UTF-8.
+    Malformed(String),
+    /// The header page's magic/version don't identify this as a file we
+    /// know how to read: either an unrelated file, or a future format
+    /// version this build predates.
+    UnrelatedFile { found_magic: [u8; 4], found_version: u8 },
+}
+
+impl std::fmt::Display for BTreeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BTreeError::Io(e) => write!(f, "io error: {}", e),
+            BTreeError::CorruptPage { page_id, expected, found } => write!(
+                f,
+                "corrupt page {}: checksum mismatch (expected {:08x}, found {:08x})",
+                page_id, expected, found
+            ),
+            BTreeError::Malformed(msg) => write!(f, "malformed node layout: {}", msg),
+            BTreeError::UnrelatedFile { found_magic, found_version } => write!(
+                f,
+                "not a btree_logs_index file (found magic {:?}, format version {})",
+                found_magic, found_version
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BTreeError {}
+
+impl From<std::io::Error> for BTreeError {
+    fn from(e: std::io::Error) -> Self {
+        BTreeError::Io(e)
+    }
+}
+
+/// Stamped into the header page so an unrelated file (or one from some other
+/// tool entirely) is rejected at `open` instead of being misread as a tree.
+pub const HEADER_MAGIC: [u8; 4] = *b"BLIX";
+
+/// On-disk format version written by this build. Bumped whenever the header
+/// or node page layout changes in a way readers need to branch on; a freshly
+/// created file is always stamped with the current value, and an existing
+/// file keeps whatever version it was opened with (see `BTree::format_version`).
+///
+/// History: 1 introduced this magic/version stamp itself (node layout
+/// unchanged from the unversioned format before it). 2 added
+/// `RecordPointer::file_id`. 3 switched key lengths, `RecordPointer`
+/// offset/length, and child page numbers to varints (see `VARINT_FORMAT_VERSION`).
+pub const FORMAT_VERSION: u8 = 3;
+
+/// Minimal table-less CRC32 (IEEE 802.3 polynomial), computed over a page's
+/// content bytes so torn writes / bit-rot are caught before they become an
+/// `unwrap` panic deep inside node decoding.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct RecordPointer {
     pub offset: u64,
     pub length: u32,
+    /// Which frame of a `CompressedLogStore` holds this record, with
+    /// `offset`/`length` then a byte range within that frame's decompressed
+    /// content. `None` means the pre-existing behavior: `offset` is a raw
+    /// byte position in an uncompressed host log file.
+    pub frame_id: Option<u64>,
+    /// Which log segment (see `BTree`'s segment registry) `offset` is a raw
+    /// byte position in, when `frame_id` is `None`. 0 is the implicit
+    /// segment every pointer meant before segments existed, so old indexes
+    /// (and their `FORMAT_VERSION` < 2 pages, which don't store this field
+    /// at all) keep working unmigrated. Meaningless when `frame_id` is `Some`:
+    /// the record lives in the compressed store instead.
+    pub file_id: u32,
 }
 
 #[derive(Debug)]
@@ -24,6 +264,11 @@ pub struct BTreeNode {
     pub keys: Vec<String>,
     pub values: Vec<RecordPointer>,
     pub children: Vec<u64>,
+    /// For a leaf: the page id of the next leaf in key order, or 0 if this is
+    /// the rightmost leaf. Unused (always 0) on internal nodes. Threading this
+    /// through every split turns the leaves into a singly linked list, so an
+    /// ordered scan can walk sideways instead of re-descending from the root.
+    pub next_leaf: u64,
 }
 
 impl BTreeNode {
@@ -33,6 +278,7 @@ impl BTreeNode {
             keys: Vec::new(),
             values: Vec::new(),
             children: Vec::new(),
+            next_leaf: 0,
         }
     }
 
@@ -42,6 +288,7 @@ impl BTreeNode {
             keys: Vec::new(),
             values: Vec::new(),
             children: Vec::new(),
+            next_leaf: 0,
         }
     }
 }
@@ -49,9 +296,8 @@ impl BTreeNode {
 #[derive(Clone)]
 pub struct BufferFrame {
     pub page_id: u64,
-    pub data: Vec<u8>, 
+    pub data: Vec<u8>,
     pub is_dirty: bool,
-    pub pin_count: usize,
 }
 
 impl BufferFrame {
@@ -60,184 +306,527 @@ impl BufferFrame {
             page_id,
             data,
             is_dirty: false,
-            pin_count: 0,
         }
     }
 }
 
+/// Number of independent cache shards. Each shard owns its own LRU and lock,
+/// so lookups for pages that hash to different shards never contend.
+const NUM_SHARDS: usize = 16;
+
+/// `BufferPool` now does positioned I/O (`read_exact_at`/`write_all_at`) rather
+/// than `seek` + `read_exact`/`write_all`, so the shared `File` cursor is never
+/// mutated and every method below takes `&self`. The LRU cache is split into
+/// `NUM_SHARDS` independently locked shards so reads/writes to disjoint pages
+/// can proceed concurrently instead of serializing on one big lock.
 pub struct BufferPool {
-    pub cache: LruCache<u64, BufferFrame>,
+    shards: Vec<Mutex<LruCache<u64, BufferFrame>>>,
     pub file: File,
+    /// Read-only mmap of `file`, populated by `enable_mmap`. `insert`/`flush`
+    /// still go through the cache-backed writable path above; this is purely
+    /// an alternate read path for `read_page_copy` to check first.
+    mmap: Mutex<Option<Mmap>>,
 }
 
 impl BufferPool {
     pub fn open_file<P: AsRef<Path>>(path: P, capacity: usize) -> std::io::Result<Self> {
-        let cap_nz = NonZeroUsize::new(capacity)
-            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "capacity must be > 0"))?;
+        if capacity == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "capacity must be > 0"));
+        }
+        let per_shard = NonZeroUsize::new((capacity / NUM_SHARDS).max(1)).unwrap();
         let file = File::options().read(true).write(true).create(true).open(path)?;
-        Ok(Self {
-            cache: LruCache::new(cap_nz),
-            file,
-        })
+        let shards = (0..NUM_SHARDS).map(|_| Mutex::new(LruCache::new(per_shard))).collect();
+        Ok(Self { shards, file, mmap: Mutex::new(None) })
+    }
+
+    /// Map `file` read-only and route subsequent `read_page_copy` calls
+    /// through the mapping instead of the cache where possible. Call again
+    /// (or call `refresh_mmap_if_enabled`, which `BTree::flush` does
+    /// automatically) after the file has grown, since a stale mapping can't
+    /// see pages appended past its original length.
+    pub fn enable_mmap(&self) -> std::io::Result<()> {
+        self.remap()
+    }
+
+    /// Re-map `file` if mmap is enabled; a no-op otherwise. `insert` grows
+    /// the file through the ordinary buffered/cache path, so an active
+    /// mapping has to be refreshed to see pages written after it was taken.
+    fn refresh_mmap_if_enabled(&self) -> std::io::Result<()> {
+        let is_enabled = self.mmap.lock().unwrap().is_some();
+        if is_enabled {
+            self.remap()?;
+        }
+        Ok(())
+    }
+
+    fn remap(&self) -> std::io::Result<()> {
+        // Safety: the map is read-only and only ever sliced within bounds
+        // checked against `file`'s length at map time; concurrent writes to
+        // `file` happening through the buffered path while mapped are the
+        // same hazard as any other process modifying a mapped file, which
+        // `memmap2`'s safety contract already leaves to the caller.
+        let mapped = unsafe { memmap2::MmapOptions::new().map(&self.file)? };
+        *self.mmap.lock().unwrap() = Some(mapped);
+        Ok(())
+    }
+
+    fn shard_for(&self, page_id: u64) -> &Mutex<LruCache<u64, BufferFrame>> {
+        &self.shards[(page_id as usize) % self.shards.len()]
     }
 
-    /// Read a page from disk and return bytes (zero-filled for beyond-file pages).
-    fn read_page_from_disk(&mut self, page_id: u64) -> std::io::Result<Vec<u8>> {
+    /// Read a page from disk via positioned I/O (zero-filled for beyond-file pages).
+    fn read_page_from_disk(&self, page_id: u64) -> std::io::Result<Vec<u8>> {
         DISK_READS.fetch_add(1, Ordering::Relaxed);
         let mut buf = vec![0u8; PAGE_SIZE];
         let offset = page_id
             .checked_mul(PAGE_SIZE as u64)
-            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "page offset overflow"))?;
+            .ok_or_else(|| std::io::Error::other("page offset overflow"))?;
 
         let file_len = self.file.metadata()?.len();
         if file_len < offset + PAGE_SIZE as u64 {
             if file_len > offset {
-                self.file.seek(SeekFrom::Start(offset))?;
                 let to_read = (file_len - offset) as usize;
-                self.file.read_exact(&mut buf[..to_read])?;
+                self.file.read_exact_at(&mut buf[..to_read], offset)?;
             }
             return Ok(buf);
         }
 
-        self.file.seek(SeekFrom::Start(offset))?;
-        self.file.read_exact(&mut buf)?;
+        self.file.read_exact_at(&mut buf, offset)?;
         Ok(buf)
     }
 
-    /// Write page bytes to disk (overwrite).
-    fn write_page_to_disk(&mut self, page_id: u64, data: &[u8]) -> std::io::Result<()> {
-         DISK_WRITES.fetch_add(1, Ordering::Relaxed);
+    /// Write page bytes to disk via positioned I/O (overwrite).
+    fn write_page_to_disk(&self, page_id: u64, data: &[u8]) -> std::io::Result<()> {
+        DISK_WRITES.fetch_add(1, Ordering::Relaxed);
         debug_assert_eq!(data.len(), PAGE_SIZE);
         let offset = page_id
             .checked_mul(PAGE_SIZE as u64)
-            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "page offset overflow"))?;
-        self.file.seek(SeekFrom::Start(offset))?;
-        self.file.write_all(data)?;
+            .ok_or_else(|| std::io::Error::other("page offset overflow"))?;
+        self.file.write_all_at(data, offset)
+    }
+
+    /// Evict one LRU frame from `shard`, writing it back first if dirty.
+    fn evict_one(&self, shard: &mut LruCache<u64, BufferFrame>) -> std::io::Result<()> {
+        if let Some((pid, frame)) = shard.pop_lru() {
+            if frame.is_dirty {
+                self.write_page_to_disk(pid, &frame.data)?;
+            }
+        }
         Ok(())
     }
 
-    /// Pin the page: ensure page is resident and increment pin_count.
-    /// DOES NOT return a reference. Use frame_mut() to access the pinned frame.
-    pub fn pin_page(&mut self, page_id: u64) -> std::io::Result<()> {
-        // Fast path: already resident
-        if let Some(frame) = self.cache.get_mut(&page_id) {
-            frame.pin_count = frame.pin_count.saturating_add(1);
-            return Ok(());
+    /// Read a page into an owned fixed-size array, going through the cache shard for `page_id`.
+    pub fn read_page_copy(&self, page_id: u64) -> std::io::Result<[u8; PAGE_SIZE]> {
+        let shard_lock = self.shard_for(page_id);
+
+        {
+            // The cache shard wins over the mmap: any page that's been
+            // written since the map was taken (or since it was last
+            // refreshed) sits here as a dirty frame, and the mapping has no
+            // way to see it until the next `flush()` remaps. Checking the
+            // shard first means a write is visible to a `read_page_copy` on
+            // the same instance without the caller having to know to flush.
+            let mut shard = shard_lock.lock().unwrap();
+            if let Some(frame) = shard.get(&page_id) {
+                let mut arr = [0u8; PAGE_SIZE];
+                arr.copy_from_slice(&frame.data);
+                return Ok(arr);
+            }
+        }
+
+        if let Some(mapped) = self.mmap.lock().unwrap().as_ref() {
+            let offset = (page_id as usize) * PAGE_SIZE;
+            if offset + PAGE_SIZE <= mapped.len() {
+                MMAP_HITS.fetch_add(1, Ordering::Relaxed);
+                let mut arr = [0u8; PAGE_SIZE];
+                arr.copy_from_slice(&mapped[offset..offset + PAGE_SIZE]);
+                return Ok(arr);
+            }
+            // Page lives past what was mapped (file grew since); fall
+            // through to the ordinary disk path below.
         }
 
-        // Load page bytes from disk (needs &mut self)
+        // Miss: read from disk without holding the shard lock, then populate the cache.
         let buf = self.read_page_from_disk(page_id)?;
 
-        // If the cache is full, evict one (may call write_page_to_disk)
-        if self.cache.len() >= self.cache.cap().get() {
-            self.evict_one()?;
+        let mut shard = shard_lock.lock().unwrap();
+        if shard.len() >= shard.cap().get() {
+            self.evict_one(&mut shard)?;
         }
+        shard.put(page_id, BufferFrame::new(page_id, buf.clone()));
 
-        // Insert and pin
-        self.cache.put(page_id, BufferFrame::new(page_id, buf));
-        if let Some(frame) = self.cache.get_mut(&page_id) {
-            frame.pin_count = 1;
+        let mut arr = [0u8; PAGE_SIZE];
+        arr.copy_from_slice(&buf);
+        Ok(arr)
+    }
+
+    /// Write a full page buffer into the pool's cache, marking it dirty.
+    pub fn write_page(&self, page_id: u64, buf: &[u8; PAGE_SIZE]) -> std::io::Result<()> {
+        let shard_lock = self.shard_for(page_id);
+        let mut shard = shard_lock.lock().unwrap();
+
+        if !shard.contains(&page_id) && shard.len() >= shard.cap().get() {
+            self.evict_one(&mut shard)?;
         }
+
+        let mut frame = BufferFrame::new(page_id, buf.to_vec());
+        frame.is_dirty = true;
+        shard.put(page_id, frame);
         Ok(())
     }
 
-    /// Unpin the page: decrement pin_count (never negative).
-    pub fn unpin_page(&mut self, page_id: u64) {
-        if let Some(frame) = self.cache.get_mut(&page_id) {
-            if frame.pin_count > 0 {
-                frame.pin_count -= 1;
+    /// Flush dirty pages in every shard to disk (does not fsync).
+    pub fn flush_all(&self) -> std::io::Result<()> {
+        for shard_lock in &self.shards {
+            let mut shard = shard_lock.lock().unwrap();
+            let dirty_pages: Vec<u64> = shard
+                .iter()
+                .filter(|(_, frame)| frame.is_dirty)
+                .map(|(pid, _)| *pid)
+                .collect();
+
+            for pid in dirty_pages {
+                if let Some(frame) = shard.get_mut(&pid) {
+                    let data = frame.data.clone();
+                    frame.is_dirty = false;
+                    self.write_page_to_disk(pid, &data)?;
+                }
             }
         }
+        Ok(())
     }
 
-    /// Access a mutable reference to the frame. Caller must ensure the page is pinned.
-    /// Returns `None` if the page is not resident.
-    pub fn frame_mut(&mut self, page_id: u64) -> Option<&mut BufferFrame> {
-        self.cache.get_mut(&page_id)
+    /// Force fsync of the underlying file
+    pub fn sync_all(&self) -> std::io::Result<()> {
+        self.file.sync_all()
     }
+}
 
-    /// Mark resident frame dirty.
-    pub fn mark_dirty(&mut self, page_id: u64) {
-        if let Some(f) = self.cache.get_mut(&page_id) {
-            f.is_dirty = true;
-        }
-    }
+/// A read-only, bounds-checked view over a page's encoded `BTreeNode` layout,
+/// in the spirit of Mercurial's dirstate-v2 `bytes_cast` decoding: it parses
+/// the leaf/internal flag and key count plus a table of per-key byte offsets
+/// up front, then yields `&str` keys and `RecordPointer`/child-id accessors
+/// by slicing the page in place. No key or value is copied until a caller
+/// actually asks for it, so a point lookup that only probes O(log n) keys
+/// during binary search never allocates a `String` for the rest.
+///
+/// Every accessor returns `Result` instead of panicking: a page that passes
+/// its checksum can still have a length field that runs past the page or key
+/// bytes that aren't valid UTF-8 (bit flip inside the content, not just the
+/// checksum), and callers like `btree_check` want to report that rather than
+/// crash.
+pub struct NodeView<'a> {
+    buf: &'a [u8; PAGE_SIZE],
+    is_leaf: bool,
+    key_count: usize,
+    /// `offsets[i]` is the byte position of key `i`'s length prefix, for `i`
+    /// in `0..key_count`. `offsets[key_count]` is the position right after
+    /// the last key's entry, which doubles as the start of the child array
+    /// for internal nodes.
+    offsets: Vec<usize>,
+    /// For a varint-format internal node: `child_offsets[i]` is the byte
+    /// position of child `i`'s varint, for `i` in `0..=key_count`, plus one
+    /// trailing sentinel at `key_count + 1` marking the end of the child
+    /// array (mirroring `offsets`'s own trailing sentinel). Empty for leaves
+    /// and for fixed-format nodes, which compute child positions by
+    /// arithmetic instead (see `child`).
+    child_offsets: Vec<usize>,
+    /// The leaf's `next_leaf` page pointer (0 = none). Always 0 for internal
+    /// nodes, which don't reserve the field.
+    next_leaf: u64,
+    /// Whether this page's `RecordPointer`s reserve a trailing `file_id`.
+    supports_file_id: bool,
+    /// Whether key lengths, `RecordPointer` offset/length, and child page
+    /// numbers on this page are LEB128 varints rather than fixed-width ints.
+    is_varint: bool,
+}
+
+impl<'a> NodeView<'a> {
+    /// Parse `buf`'s header and per-key offset table, checking every length
+    /// field against the page bounds as it goes. Does not validate key UTF-8;
+    /// that is checked lazily by `key`. On a fixed-width page it reads no
+    /// value/child bytes either; on a varint page it has to decode each
+    /// value's and child's varints to find where the next one starts, but
+    /// keeps only their positions. `format_version` picks the `RecordPointer`
+    /// encoding (see `record_pointer_size`) and whether lengths/ids are
+    /// varints (see `VARINT_FORMAT_VERSION`) this page was written with.
+    pub fn parse(buf: &'a [u8; PAGE_SIZE], format_version: u8) -> Result<Self, BTreeError> {
+        let is_leaf = buf[CHECKSUM_SIZE] == 1;
+        let key_count =
+            u16::from_le_bytes(buf[CHECKSUM_SIZE + 1..CHECKSUM_SIZE + 3].try_into().unwrap()) as usize;
+        let record_pointer_size = record_pointer_size(format_version);
+        let supports_file_id = format_version >= FILE_ID_FORMAT_VERSION;
+        let is_varint = format_version >= VARINT_FORMAT_VERSION;
+
+        let mut pos: usize = CHECKSUM_SIZE + 3;
+        let next_leaf = if is_leaf {
+            let v = u64::from_le_bytes(buf[pos..pos + 8].try_into().unwrap());
+            pos += 8;
+            v
+        } else {
+            0
+        };
 
-    /// Evict one unpinned LRU frame; write back if dirty.
-    fn evict_one(&mut self) -> std::io::Result<()> {
-        let capacity = self.cache.cap().get();
-        for _ in 0..capacity.saturating_add(1) {
-            if let Some((pid, frame)) = self.cache.pop_lru() {
-                if frame.pin_count == 0 {
-                    if frame.is_dirty {
-                        self.write_page_to_disk(pid, &frame.data)?;
+        let mut offsets = Vec::with_capacity(key_count + 1);
+
+        if is_varint {
+            for _ in 0..key_count {
+                offsets.push(pos);
+                let (klen, klen_bytes) = read_varint(buf, pos)?;
+                pos += klen_bytes;
+                if pos + klen as usize > PAGE_SIZE {
+                    return Err(BTreeError::Malformed(format!(
+                        "key bytes at byte {} (length {}) run past the page",
+                        pos, klen
+                    )));
+                }
+                pos += klen as usize;
+
+                if is_leaf {
+                    let (_offset, n1) = read_varint(buf, pos)?;
+                    pos += n1;
+                    let (_length, n2) = read_varint(buf, pos)?;
+                    pos += n2;
+                    let trailer = 8 + if supports_file_id { 4 } else { 0 };
+                    if pos + trailer > PAGE_SIZE {
+                        return Err(BTreeError::Malformed(format!(
+                            "record pointer trailer at byte {} runs past the page",
+                            pos
+                        )));
                     }
-                    return Ok(());
-                } else {
-                    // reinstate pinned frame as MRU
-                    self.cache.put(pid, frame);
+                    pos += trailer;
+                }
+            }
+        } else {
+            for _ in 0..key_count {
+                offsets.push(pos);
+                if pos + 2 > PAGE_SIZE {
+                    return Err(BTreeError::Malformed(format!(
+                        "key length prefix at byte {} runs past the page",
+                        pos
+                    )));
+                }
+                let klen = u16::from_le_bytes(buf[pos..pos + 2].try_into().unwrap()) as usize;
+                let entry_len = 2 + klen + if is_leaf { record_pointer_size } else { 0 };
+                if pos + entry_len > PAGE_SIZE {
+                    return Err(BTreeError::Malformed(format!(
+                        "key entry at byte {} (length {}) runs past the page",
+                        pos, entry_len
+                    )));
+                }
+                pos += entry_len;
+            }
+        }
+        offsets.push(pos);
+
+        let mut child_offsets = Vec::new();
+        if !is_leaf {
+            if is_varint {
+                child_offsets.reserve(key_count + 2);
+                for _ in 0..=key_count {
+                    child_offsets.push(pos);
+                    let (_child, n) = read_varint(buf, pos)?;
+                    pos += n;
                 }
+                child_offsets.push(pos);
             } else {
-                break;
+                let children_len = (key_count + 1) * 8;
+                if pos + children_len > PAGE_SIZE {
+                    return Err(BTreeError::Malformed(format!(
+                        "child array at byte {} (length {}) runs past the page",
+                        pos, children_len
+                    )));
+                }
             }
         }
-        Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "no evictable page (all pages pinned)",
-        ))
+
+        Ok(NodeView { buf, is_leaf, key_count, offsets, child_offsets, next_leaf, supports_file_id, is_varint })
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.is_leaf
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.key_count
     }
 
-    /// Read a page into an owned fixed-size array (pin/unpin internally).
-    pub fn read_page_copy(&mut self, page_id: u64) -> std::io::Result<[u8; PAGE_SIZE]> {
-        self.pin_page(page_id)?;
-        let arr = {
-            let frame = self.frame_mut(page_id).expect("frame should be present after pin");
-            let mut a = [0u8; PAGE_SIZE];
-            a.copy_from_slice(&frame.data);
-            a
+    /// The leaf's successor page in key order (0 = none). 0 on internal nodes.
+    pub fn next_leaf(&self) -> u64 {
+        self.next_leaf
+    }
+
+    /// Decode the length prefix at `start` (a key's length), returning the
+    /// length and how many bytes the prefix itself took.
+    fn decode_len_prefix(&self, start: usize) -> Result<(usize, usize), BTreeError> {
+        if self.is_varint {
+            let (len, n) = read_varint(self.buf, start)?;
+            Ok((len as usize, n))
+        } else {
+            let len = u16::from_le_bytes(self.buf[start..start + 2].try_into().unwrap()) as usize;
+            Ok((len, 2))
+        }
+    }
+
+    /// The `i`-th key, borrowed straight out of the page buffer.
+    pub fn key(&self, i: usize) -> Result<&'a str, BTreeError> {
+        let start = self.offsets[i];
+        let (klen, prefix_len) = self.decode_len_prefix(start)?;
+        let bytes = &self.buf[start + prefix_len..start + prefix_len + klen];
+        std::str::from_utf8(bytes)
+            .map_err(|_| BTreeError::Malformed(format!("key at byte {} is not valid utf-8", start)))
+    }
+
+    /// The `i`-th leaf value. Errors if called on an internal node.
+    pub fn value(&self, i: usize) -> Result<RecordPointer, BTreeError> {
+        if !self.is_leaf {
+            return Err(BTreeError::Malformed("value() called on an internal node".into()));
+        }
+        let start = self.offsets[i];
+        let (klen, prefix_len) = self.decode_len_prefix(start)?;
+        let vpos = start + prefix_len + klen;
+
+        let (offset, length, trailer_pos) = if self.is_varint {
+            let (offset, n1) = read_varint(self.buf, vpos)?;
+            let (length, n2) = read_varint(self.buf, vpos + n1)?;
+            (offset, length as u32, vpos + n1 + n2)
+        } else {
+            let offset = u64::from_le_bytes(self.buf[vpos..vpos + 8].try_into().unwrap());
+            let length = u32::from_le_bytes(self.buf[vpos + 8..vpos + 12].try_into().unwrap());
+            (offset, length, vpos + 12)
         };
-        self.unpin_page(page_id);
-        Ok(arr)
+
+        let frame_id_raw = u64::from_le_bytes(self.buf[trailer_pos..trailer_pos + 8].try_into().unwrap());
+        let frame_id = if frame_id_raw == NO_FRAME_ID { None } else { Some(frame_id_raw) };
+        let file_id = if self.supports_file_id {
+            u32::from_le_bytes(self.buf[trailer_pos + 8..trailer_pos + 12].try_into().unwrap())
+        } else {
+            0
+        };
+        Ok(RecordPointer { offset, length, frame_id, file_id })
     }
 
-    /// Write full page buffer into the pool (pin/unpin internally).
-    pub fn write_page(&mut self, page_id: u64, buf: &[u8; PAGE_SIZE]) -> std::io::Result<()> {
-        self.pin_page(page_id)?;
-        {
-            let frame = self.frame_mut(page_id).expect("frame should be present after pin");
-            frame.data.copy_from_slice(buf);
-            frame.is_dirty = true;
+    /// The `i`-th child page id. Errors if called on a leaf node.
+    pub fn child(&self, i: usize) -> Result<u64, BTreeError> {
+        if self.is_leaf {
+            return Err(BTreeError::Malformed("child() called on a leaf node".into()));
         }
-        self.unpin_page(page_id);
-        Ok(())
+        if i > self.key_count {
+            return Err(BTreeError::Malformed(format!("child index {} out of range", i)));
+        }
+        if self.is_varint {
+            let (child, _) = read_varint(self.buf, self.child_offsets[i])?;
+            Ok(child)
+        } else {
+            let base = self.offsets[self.key_count] + i * 8;
+            Ok(u64::from_le_bytes(self.buf[base..base + 8].try_into().unwrap()))
+        }
+    }
+
+    /// Binary search for `key` among this node's keys, touching only the
+    /// `O(log n)` keys the probe actually visits. Mirrors `[T]::binary_search`:
+    /// `Ok(i)` is an exact match at `i`, `Err(i)` is the insertion point.
+    pub fn binary_search_key(&self, key: &str) -> Result<Result<usize, usize>, BTreeError> {
+        let mut lo = 0usize;
+        let mut hi = self.key_count;
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.key(mid)?.cmp(key) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Equal => return Ok(Ok(mid)),
+                std::cmp::Ordering::Greater => hi = mid,
+            }
+        }
+        Ok(Err(lo))
     }
+}
 
-    /// Flush dirty pages to disk (does not fsync).
-    pub fn flush_all(&mut self) -> std::io::Result<()> {
-        // collect keys first to avoid double-borrow
-        let keys: Vec<u64> = self.cache.iter().map(|(k, _)| *k).collect();
-        for pid in keys {
-            if let Some(frame) = self.cache.get_mut(&pid) {
-    if frame.is_dirty {
-        // Step 1: copy needed data
-        let data = frame.data.clone();
-        // Step 2: mark clean inside cache
-        frame.is_dirty = false;
-        // Step 3: release &mut frame (borrow ends here)
-        drop(frame);
+/// Decode a `BTreeNode` out of a raw page buffer (content starts after the
+/// `CHECKSUM_SIZE`-byte checksum header), via `NodeView`. Shared by
+/// `read_node` and `try_read_node` so both go through the same layout.
+fn decode_node(buf: &[u8; PAGE_SIZE], format_version: u8) -> Result<BTreeNode, BTreeError> {
+    let view = NodeView::parse(buf, format_version)?;
+
+    let mut keys = Vec::with_capacity(view.key_count());
+    let mut values = Vec::with_capacity(view.key_count());
+    for i in 0..view.key_count() {
+        keys.push(view.key(i)?.to_string());
+        if view.is_leaf() {
+            values.push(view.value(i)?);
+        }
+    }
 
-        // Step 4: now safe to borrow &mut self again for disk write
-        self.write_page_to_disk(pid, &data)?;
+    let mut children = Vec::new();
+    if !view.is_leaf() {
+        for i in 0..=view.key_count() {
+            children.push(view.child(i)?);
+        }
     }
+
+    Ok(BTreeNode { is_leaf: view.is_leaf(), keys, values, children, next_leaf: view.next_leaf() })
 }
 
+/// Lazy ordered iteration over leaves linked by `next_leaf`, produced by
+/// `BTree::range`/`BTree::prefix`. Holds one decoded leaf's keys/values at a
+/// time rather than the whole scan, so a long range doesn't buffer it all
+/// into a `Vec` up front the way `range_scan`/`prefix_scan` do.
+pub struct RangeIter<'a> {
+    tree: &'a BTree,
+    keys: Vec<String>,
+    values: Vec<RecordPointer>,
+    pos: usize,
+    next_leaf: u64,
+    /// Returns `true` once a key is past the end of the scan (beyond the
+    /// upper bound, or no longer matching the prefix).
+    stop: Box<dyn Fn(&str) -> bool + 'a>,
+    done: bool,
+}
+
+impl<'a> RangeIter<'a> {
+    fn empty(tree: &'a BTree) -> Self {
+        RangeIter {
+            tree,
+            keys: Vec::new(),
+            values: Vec::new(),
+            pos: 0,
+            next_leaf: 0,
+            stop: Box::new(|_: &str| false),
+            done: true,
         }
-        Ok(())
     }
+}
 
-    /// Force fsync of the underlying file
-    pub fn sync_all(&mut self) -> std::io::Result<()> {
-        self.file.sync_all()
+impl<'a> Iterator for RangeIter<'a> {
+    type Item = (String, RecordPointer);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        loop {
+            if self.pos < self.keys.len() {
+                if (self.stop)(&self.keys[self.pos]) {
+                    self.done = true;
+                    return None;
+                }
+                let key = self.keys[self.pos].clone();
+                let val = self.values[self.pos];
+                self.pos += 1;
+                return Some((key, val));
+            }
+
+            if self.next_leaf == 0 {
+                self.done = true;
+                return None;
+            }
+
+            let node = self.tree.read_node(self.next_leaf);
+            self.next_leaf = node.next_leaf;
+            self.keys = node.keys;
+            self.values = node.values;
+            self.pos = 0;
+        }
     }
 }
 
@@ -246,6 +835,21 @@ pub struct BTree {
     pub pool: BufferPool,
     pub root_page: u64,
     pub next_page: u64,
+    /// Head of the on-disk free-page list (0 = empty). Pages freed by a merge
+    /// during `delete` are pushed here; `alloc_page` pops from here first.
+    pub free_list_head: u64,
+    /// On-disk format version this file was opened with (see `HEADER_MAGIC`
+    /// / `FORMAT_VERSION` above). Sticky for the life of the open file: a
+    /// freshly created file gets the current `FORMAT_VERSION`, an existing
+    /// one keeps whatever it was stamped with, since its pages are already
+    /// encoded to that version's layout and `update_header` must not claim
+    /// a newer version than the node pages actually match.
+    pub format_version: u8,
+    /// Maps a `RecordPointer::file_id` to the log file it was read from,
+    /// decoded from the header page. Lets the index span rotated/rolled log
+    /// files without reindexing entries pointing at earlier ones; see
+    /// `register_segment` and `read_log_entry`.
+    pub segments: HashMap<u32, PathBuf>,
 }
 
 impl BTree {
@@ -254,71 +858,252 @@ impl BTree {
         Self::open_with_capacity(path, 1024).expect("open btree")
     }
 
+    /// Open BTree backed by a read-only mmap of the index file: `read_node`
+    /// ends up slicing pages straight out of the mapped region via
+    /// `BufferPool::read_page_copy` instead of issuing a `read_exact_at` per
+    /// page. `insert`/`flush` are unaffected — they still go through the
+    /// ordinary buffered/cache path, and `flush` remaps afterward so the
+    /// mapping stays current as the file grows.
+    pub fn memmap(path: &Path) -> std::io::Result<Self> {
+        let tree = Self::open_with_capacity(path, 1024)?;
+        tree.pool.enable_mmap()?;
+        Ok(tree)
+    }
+
     pub fn open_with_capacity(path: &Path, capacity: usize) -> std::io::Result<Self> {
-        let mut pool = BufferPool::open_file(path, capacity)?;
+        let pool = BufferPool::open_file(path, capacity)?;
 
         // Read header (page 0). If file empty, read_page_from_disk will return zeros.
         let header = pool.read_page_copy(0)?;
-        let root = u64::from_le_bytes(header[0..8].try_into().unwrap());
-        let next = u64::from_le_bytes(header[8..16].try_into().unwrap());
         let file_len = pool.file.metadata()?.len();
+        let is_fresh = file_len == 0;
+
+        if !is_fresh {
+            let expected = u32::from_le_bytes(header[0..CHECKSUM_SIZE].try_into().unwrap());
+            let found = crc32(&header[CHECKSUM_SIZE..]);
+            if expected != found {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "{}",
+                        BTreeError::CorruptPage { page_id: 0, expected, found }
+                    ),
+                ));
+            }
+        }
+
+        let root = u64::from_le_bytes(header[CHECKSUM_SIZE..CHECKSUM_SIZE + 8].try_into().unwrap());
+        let next = u64::from_le_bytes(header[CHECKSUM_SIZE + 8..CHECKSUM_SIZE + 16].try_into().unwrap());
+        let free_list_head = u64::from_le_bytes(header[CHECKSUM_SIZE + 16..CHECKSUM_SIZE + 24].try_into().unwrap());
         let actual_pages = if file_len == 0 { 0 } else { file_len / PAGE_SIZE as u64 };
         let reconciled_next = if actual_pages == 0 { 1 } else { actual_pages };
         let next_page_final = if next == 0 { 1 } else { std::cmp::min(next, reconciled_next) };
+        let is_blank_header = root == 0 && next == 0 && actual_pages == 0;
 
-        let (root_page, next_page) = if file_len == 0 || (root == 0 && next == 0 && actual_pages == 0) {
+        let (root_page, next_page, free_list_head, format_version) = if is_fresh || is_blank_header {
             let mut header_buf = [0u8; PAGE_SIZE];
-            header_buf[0..8].copy_from_slice(&0u64.to_le_bytes());
-            header_buf[8..16].copy_from_slice(&1u64.to_le_bytes());
+            header_buf[CHECKSUM_SIZE..CHECKSUM_SIZE + 8].copy_from_slice(&0u64.to_le_bytes());
+            header_buf[CHECKSUM_SIZE + 8..CHECKSUM_SIZE + 16].copy_from_slice(&1u64.to_le_bytes());
+            header_buf[CHECKSUM_SIZE + 16..CHECKSUM_SIZE + 24].copy_from_slice(&0u64.to_le_bytes());
+            header_buf[CHECKSUM_SIZE + 24..CHECKSUM_SIZE + 28].copy_from_slice(&HEADER_MAGIC);
+            header_buf[CHECKSUM_SIZE + 28] = FORMAT_VERSION;
+            let sum = crc32(&header_buf[CHECKSUM_SIZE..]);
+            header_buf[0..CHECKSUM_SIZE].copy_from_slice(&sum.to_le_bytes());
             pool.write_page(0, &header_buf)?;
             pool.sync_all()?;
-            (0u64, 1u64)
+            (0u64, 1u64, 0u64, FORMAT_VERSION)
         } else {
-            (root, next_page_final)
+            let found_magic: [u8; 4] = header[CHECKSUM_SIZE + 24..CHECKSUM_SIZE + 28].try_into().unwrap();
+            let found_version = header[CHECKSUM_SIZE + 28];
+
+            let format_version = if found_magic == HEADER_MAGIC {
+                if found_version > FORMAT_VERSION {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("{}", BTreeError::UnrelatedFile { found_magic, found_version }),
+                    ));
+                }
+                found_version
+            } else if found_magic == [0u8; 4] && found_version == 0 {
+                // Predates the magic/version stamp: a real, pre-existing
+                // index whose header simply never reserved these bytes.
+                0
+            } else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{}", BTreeError::UnrelatedFile { found_magic, found_version }),
+                ));
+            };
+
+            (root, next_page_final, free_list_head, format_version)
         };
 
+        let segments = read_segment_table(&header);
+
         Ok(BTree {
             pool,
             root_page,
             next_page,
+            free_list_head,
+            format_version,
+            segments,
         })
     }
 
+    /// Allocate a page, reusing a freed page from the free list before
+    /// growing the file via `next_page`.
     pub fn alloc_page(&mut self) -> u64 {
+        if self.free_list_head != 0 {
+            let page_id = self.free_list_head;
+            let buf = self.read_raw_page(page_id);
+            let next_free = u64::from_le_bytes(buf[CHECKSUM_SIZE..CHECKSUM_SIZE + 8].try_into().unwrap());
+            self.free_list_head = next_free;
+            self.update_header();
+            return page_id;
+        }
+
         let new_page = self.next_page;
         let zero = [0u8; PAGE_SIZE];
-        self.pool.write_page(new_page, &zero).expect("alloc write");
+        self.write_raw_page(new_page, &zero);
         self.next_page += 1;
         self.update_header();
         new_page
     }
 
-    fn write_raw_page(&mut self, page_id: u64, buf: &[u8; PAGE_SIZE]) {
+    /// Push a page onto the free list so a future `alloc_page` reuses it
+    /// instead of growing the file. The page's own content is overwritten
+    /// with the previous free-list head, forming a singly linked chain.
+    fn push_free_page(&mut self, page_id: u64) {
+        let mut buf = [0u8; PAGE_SIZE];
+        buf[CHECKSUM_SIZE..CHECKSUM_SIZE + 8].copy_from_slice(&self.free_list_head.to_le_bytes());
+        self.write_raw_page(page_id, &buf);
+        self.free_list_head = page_id;
+        self.update_header();
+    }
+
+    /// Stamp `buf` with a checksum over its content (everything past
+    /// `CHECKSUM_SIZE`) and write it out.
+    fn write_raw_page(&self, page_id: u64, buf: &[u8; PAGE_SIZE]) {
+        let mut stamped = *buf;
+        let sum = crc32(&stamped[CHECKSUM_SIZE..]);
+        stamped[0..CHECKSUM_SIZE].copy_from_slice(&sum.to_le_bytes());
         self.pool
-            .write_page(page_id, buf)
+            .write_page(page_id, &stamped)
             .expect("write_raw_page failed");
     }
 
-    fn read_raw_page(&mut self, page_id: u64) -> [u8; PAGE_SIZE] {
-        self.pool
-            .read_page_copy(page_id)
-            .expect("read_raw_page failed")
+    /// Read a page back and verify its checksum, panicking on mismatch. Used by
+    /// the hot insert/search paths, which historically trusted page bytes outright.
+    fn read_raw_page(&self, page_id: u64) -> [u8; PAGE_SIZE] {
+        self.try_read_raw_page(page_id)
+            .unwrap_or_else(|e| panic!("read_raw_page failed: {}", e))
+    }
+
+    /// Read a page back and verify its checksum, returning a typed
+    /// `BTreeError::CorruptPage` instead of panicking on mismatch.
+    fn try_read_raw_page(&self, page_id: u64) -> Result<[u8; PAGE_SIZE], BTreeError> {
+        let buf = self.pool.read_page_copy(page_id)?;
+        let expected = u32::from_le_bytes(buf[0..CHECKSUM_SIZE].try_into().unwrap());
+        let found = crc32(&buf[CHECKSUM_SIZE..]);
+        if expected != found {
+            return Err(BTreeError::CorruptPage { page_id, expected, found });
+        }
+        Ok(buf)
+    }
+
+    /// Like `read_node`, but surfaces checksum corruption as an error instead
+    /// of panicking, for callers (e.g. `btree_check`) that want to diagnose
+    /// rather than crash on a damaged index.
+    pub fn try_read_node(&self, page_id: u64) -> Result<BTreeNode, BTreeError> {
+        let buf = self.try_read_raw_page(page_id)?;
+        decode_node(&buf, self.format_version)
     }
 
     fn update_header(&mut self) {
         let mut header = [0u8; PAGE_SIZE];
-        header[0..8].copy_from_slice(&self.root_page.to_le_bytes());
-        header[8..16].copy_from_slice(&self.next_page.to_le_bytes());
+        header[CHECKSUM_SIZE..CHECKSUM_SIZE + 8].copy_from_slice(&self.root_page.to_le_bytes());
+        header[CHECKSUM_SIZE + 8..CHECKSUM_SIZE + 16].copy_from_slice(&self.next_page.to_le_bytes());
+        header[CHECKSUM_SIZE + 16..CHECKSUM_SIZE + 24].copy_from_slice(&self.free_list_head.to_le_bytes());
+        header[CHECKSUM_SIZE + 24..CHECKSUM_SIZE + 28].copy_from_slice(&HEADER_MAGIC);
+        header[CHECKSUM_SIZE + 28] = self.format_version;
+        write_segment_table(&mut header, &self.segments)
+            .expect("segment registry no longer fits in the header page");
         self.write_raw_page(0, &header);
     }
 
+    /// Add (or repoint) a segment in the registry and persist it to the
+    /// header page immediately, so a crash right after doesn't lose it.
+    /// Fails without registering anything if the registry no longer fits in
+    /// the header page's reserved region.
+    pub fn register_segment(&mut self, file_id: u32, path: PathBuf) -> io::Result<()> {
+        let mut trial = self.segments.clone();
+        trial.insert(file_id, path);
+        let mut probe = [0u8; PAGE_SIZE];
+        write_segment_table(&mut probe, &trial)?;
+        self.segments = trial;
+        self.update_header();
+        // Unlike an ordinary mutation (which waits for the caller's next
+        // `flush()`), the header page is pushed to disk and fsynced here so
+        // the promise in this function's doc comment actually holds.
+        self.pool.flush_all()?;
+        self.pool.sync_all()?;
+        self.pool.refresh_mmap_if_enabled()?;
+        Ok(())
+    }
+
+    /// Look up the log file path a `RecordPointer::file_id` refers to.
+    pub fn segment_path(&self, file_id: u32) -> Option<&Path> {
+        self.segments.get(&file_id).map(PathBuf::as_path)
+    }
+
+    /// Resolve `ptr` to its log line. `file_id` 0 falls back to
+    /// `default_log_path` when no segment is registered for it, so callers
+    /// that never call `register_segment` (and indexes predating segments
+    /// entirely, whose pointers are all implicitly `file_id: 0`) keep working
+    /// unmigrated. Any other `file_id` must be registered.
+    pub fn read_log_entry(&self, ptr: RecordPointer, default_log_path: &Path) -> io::Result<String> {
+        let path = match self.segment_path(ptr.file_id) {
+            Some(p) => p,
+            None if ptr.file_id == 0 => default_log_path,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no log segment registered for file_id {}", ptr.file_id),
+                ))
+            }
+        };
+
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(ptr.offset))?;
+        let mut buf = vec![0u8; ptr.length as usize];
+        file.read_exact(&mut buf)?;
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+
     pub fn write_node(&mut self, page_id: u64, node: &BTreeNode) {
+        let buf = if self.format_version >= VARINT_FORMAT_VERSION {
+            self.encode_node_varint(node)
+        } else {
+            self.encode_node_fixed(node)
+        };
+        self.write_raw_page(page_id, &buf);
+    }
+
+    /// Encode a node with the pre-varint fixed-width layout: a 2-byte key
+    /// length and (for a leaf) a fixed-size `RecordPointer`, per key; a flat
+    /// 8-byte-per-entry child array for internal nodes.
+    fn encode_node_fixed(&self, node: &BTreeNode) -> [u8; PAGE_SIZE] {
         let mut buf = [0u8; PAGE_SIZE];
 
-        buf[0] = if node.is_leaf { 1 } else { 0 };
-        buf[1..3].copy_from_slice(&(node.keys.len() as u16).to_le_bytes());
+        buf[CHECKSUM_SIZE] = if node.is_leaf { 1 } else { 0 };
+        buf[CHECKSUM_SIZE + 1..CHECKSUM_SIZE + 3].copy_from_slice(&(node.keys.len() as u16).to_le_bytes());
 
-        let mut pos: usize = 3;
+        let mut pos: usize = CHECKSUM_SIZE + 3;
+
+        if node.is_leaf {
+            buf[pos..pos + 8].copy_from_slice(&node.next_leaf.to_le_bytes());
+            pos += 8;
+        }
 
         for i in 0..node.keys.len() {
             let kb = node.keys[i].as_bytes();
@@ -335,6 +1120,13 @@ impl BTree {
                 pos += 8;
                 buf[pos..pos + 4].copy_from_slice(&node.values[i].length.to_le_bytes());
                 pos += 4;
+                let frame_id_raw = node.values[i].frame_id.unwrap_or(NO_FRAME_ID);
+                buf[pos..pos + 8].copy_from_slice(&frame_id_raw.to_le_bytes());
+                pos += 8;
+                if self.format_version >= FILE_ID_FORMAT_VERSION {
+                    buf[pos..pos + 4].copy_from_slice(&node.values[i].file_id.to_le_bytes());
+                    pos += 4;
+                }
             }
         }
 
@@ -345,47 +1137,89 @@ impl BTree {
             }
         }
 
-        self.write_raw_page(page_id, &buf);
+        buf
     }
 
-    pub fn read_node(&mut self, page_id: u64) -> BTreeNode {
-        let buf = self.read_raw_page(page_id);
-
-        let is_leaf = buf[0] == 1;
-        let key_count = u16::from_le_bytes(buf[1..3].try_into().unwrap()) as usize;
+    /// Encode a node with the varint layout (`VARINT_FORMAT_VERSION` and
+    /// up): key lengths, `RecordPointer` offset/length, and child page
+    /// numbers are LEB128 varints packed back-to-back instead of padded to a
+    /// fixed width, so a page's used bytes shrink for the same `MAX_KEYS`
+    /// entries. `ORDER`/`MAX_KEYS` are unchanged, so a node still splits on
+    /// key count, not on the page filling up — the saved bytes are headroom,
+    /// not additional fan-out. `frame_id`/`file_id` stay fixed-width (see
+    /// `VARINT_FORMAT_VERSION`).
+    fn encode_node_varint(&self, node: &BTreeNode) -> [u8; PAGE_SIZE] {
+        let mut buf = [0u8; PAGE_SIZE];
 
-        let mut pos: usize = 3;
-        let mut keys = Vec::with_capacity(key_count);
-        let mut values = Vec::with_capacity(key_count);
+        buf[CHECKSUM_SIZE] = if node.is_leaf { 1 } else { 0 };
+        buf[CHECKSUM_SIZE + 1..CHECKSUM_SIZE + 3].copy_from_slice(&(node.keys.len() as u16).to_le_bytes());
 
-        for _ in 0..key_count {
-            let klen = u16::from_le_bytes(buf[pos..pos + 2].try_into().unwrap()) as usize;
-            pos += 2;
+        let mut pos: usize = CHECKSUM_SIZE + 3;
 
-            let key = String::from_utf8(buf[pos..pos + klen].to_vec()).unwrap();
-            pos += klen;
+        if node.is_leaf {
+            buf[pos..pos + 8].copy_from_slice(&node.next_leaf.to_le_bytes());
+            pos += 8;
+        }
 
-            keys.push(key);
+        for i in 0..node.keys.len() {
+            let kb = node.keys[i].as_bytes();
+            pos = write_varint(&mut buf, pos, kb.len() as u64);
+            buf[pos..pos + kb.len()].copy_from_slice(kb);
+            pos += kb.len();
 
-            if is_leaf {
-                let offset = u64::from_le_bytes(buf[pos..pos + 8].try_into().unwrap());
+            if node.is_leaf {
+                pos = write_varint(&mut buf, pos, node.values[i].offset);
+                pos = write_varint(&mut buf, pos, node.values[i].length as u64);
+                let frame_id_raw = node.values[i].frame_id.unwrap_or(NO_FRAME_ID);
+                buf[pos..pos + 8].copy_from_slice(&frame_id_raw.to_le_bytes());
                 pos += 8;
-                let length = u32::from_le_bytes(buf[pos..pos + 4].try_into().unwrap());
-                pos += 4;
-                values.push(RecordPointer { offset, length });
+                if self.format_version >= FILE_ID_FORMAT_VERSION {
+                    buf[pos..pos + 4].copy_from_slice(&node.values[i].file_id.to_le_bytes());
+                    pos += 4;
+                }
             }
         }
 
-        let mut children = Vec::new();
-        if !is_leaf {
-            for _ in 0..(key_count + 1) {
-                let child = u64::from_le_bytes(buf[pos..pos + 8].try_into().unwrap());
-                pos += 8;
-                children.push(child);
+        if !node.is_leaf {
+            for child in &node.children {
+                pos = write_varint(&mut buf, pos, *child);
             }
         }
 
-        BTreeNode { is_leaf, keys, values, children }
+        buf
+    }
+
+    pub fn read_node(&self, page_id: u64) -> BTreeNode {
+        let buf = self.read_raw_page(page_id);
+        decode_node(&buf, self.format_version).unwrap_or_else(|e| panic!("read_node: {}", e))
+    }
+
+    /// Encode a page buffer's header (leaf flag + key count) followed by
+    /// pre-encoded key and child byte ranges copied straight out of a source
+    /// page. Used by `split_child` so the halves of a split node are built
+    /// from raw bytes instead of decoding every key into an owned `String`
+    /// just to re-encode it right back.
+    fn build_raw_node(
+        is_leaf: bool,
+        key_count: u16,
+        next_leaf: u64,
+        key_bytes: &[u8],
+        child_bytes: &[u8],
+    ) -> [u8; PAGE_SIZE] {
+        let mut buf = [0u8; PAGE_SIZE];
+        buf[CHECKSUM_SIZE] = if is_leaf { 1 } else { 0 };
+        buf[CHECKSUM_SIZE + 1..CHECKSUM_SIZE + 3].copy_from_slice(&key_count.to_le_bytes());
+
+        let mut pos = CHECKSUM_SIZE + 3;
+        if is_leaf {
+            buf[pos..pos + 8].copy_from_slice(&next_leaf.to_le_bytes());
+            pos += 8;
+        }
+        buf[pos..pos + key_bytes.len()].copy_from_slice(key_bytes);
+        pos += key_bytes.len();
+        buf[pos..pos + child_bytes.len()].copy_from_slice(child_bytes);
+
+        buf
     }
 
     pub fn split_child(&mut self, parent_page: u64, index: usize) {
@@ -393,35 +1227,61 @@ impl BTree {
 
         let mut parent = self.read_node(parent_page);
         let child_page = parent.children[index];
-        let mut y = self.read_node(child_page);
 
-        if y.keys.len() != MAX_KEYS {
+        let child_buf = self.read_raw_page(child_page);
+        let view = NodeView::parse(&child_buf, self.format_version).unwrap_or_else(|e| panic!("split_child: {}", e));
+
+        if view.key_count() != MAX_KEYS {
             return;
         }
 
+        let is_leaf = view.is_leaf();
+        let middle_key = view.key(t - 1).unwrap_or_else(|e| panic!("split_child: {}", e)).to_string();
+        // The right half keeps whatever the original leaf pointed to next;
+        // the left half (staying on `child_page`) now points at the new
+        // right half, splicing `z_page` into the leaf chain between them.
+        let old_next_leaf = view.next_leaf();
         let z_page = self.alloc_page();
-        let mut z = if y.is_leaf { BTreeNode::new_leaf() } else { BTreeNode::new_internal() };
 
-        let middle_key = y.keys[t - 1].clone();
+        // Keys [0, t-1) stay on the left (the original page). For an internal
+        // node, the middle key at `t - 1` is promoted into the parent as a
+        // separator and dropped from both children, since internal nodes
+        // carry no values. A leaf node's middle key carries a `RecordPointer`
+        // that lives nowhere else, so the right half keeps keys [t - 1, MAX_KEYS)
+        // and the parent gets its own copy of the key as a valueless separator.
+        let right_start = if is_leaf { t - 1 } else { t };
+        let left_key_bytes = &child_buf[view.offsets[0]..view.offsets[t - 1]];
+        let right_key_bytes = &child_buf[view.offsets[right_start]..view.offsets[MAX_KEYS]];
+
+        let (left_child_bytes, right_child_bytes): (&[u8], &[u8]) = if is_leaf {
+            (&[], &[])
+        } else if self.format_version >= VARINT_FORMAT_VERSION {
+            // Children are variable-length varints here, so their byte
+            // ranges come from `child_offsets` rather than `i * 8` arithmetic.
+            let co = &view.child_offsets;
+            (&child_buf[co[0]..co[t]], &child_buf[co[t]..co[MAX_KEYS + 1]])
+        } else {
+            let children_start = view.offsets[MAX_KEYS];
+            (
+                &child_buf[children_start..children_start + t * 8],
+                &child_buf[children_start + t * 8..children_start + (MAX_KEYS + 1) * 8],
+            )
+        };
 
-        if y.is_leaf {
-            z.keys = y.keys.split_off(t);
-            z.values = y.values.split_off(t);
+        let left_buf = Self::build_raw_node(is_leaf, (t - 1) as u16, z_page, left_key_bytes, left_child_bytes);
+        let right_buf = Self::build_raw_node(
+            is_leaf,
+            (MAX_KEYS - right_start) as u16,
+            old_next_leaf,
+            right_key_bytes,
+            right_child_bytes,
+        );
 
-            y.keys.truncate(t - 1);
-            y.values.truncate(t - 1);
-        } else {
-            z.keys = y.keys.split_off(t);
-            z.children = y.children.split_off(t);
-            y.keys.truncate(t - 1);
-            y.children.truncate(t);
-        }
+        self.write_raw_page(child_page, &left_buf);
+        self.write_raw_page(z_page, &right_buf);
 
         parent.children.insert(index + 1, z_page);
         parent.keys.insert(index, middle_key);
-
-        self.write_node(child_page, &y);
-        self.write_node(z_page, &z);
         self.write_node(parent_page, &parent);
     }
 
@@ -491,38 +1351,573 @@ impl BTree {
     }
 
     /// search
-    pub fn search(&mut self, key: &str) -> Option<RecordPointer> {
+    pub fn search(&self, key: &str) -> Option<RecordPointer> {
         if self.root_page == 0 {
             return None;
         }
         self.search_node(self.root_page, key)
     }
 
-    fn search_node(&mut self, page_id: u64, key: &str) -> Option<RecordPointer> {
+    /// Point lookup via `NodeView`: binary search only reads the `O(log n)`
+    /// keys it actually probes, so a successful search never allocates a
+    /// `String` or a `Vec` for the nodes it passes through.
+    fn search_node(&self, page_id: u64, key: &str) -> Option<RecordPointer> {
+        let buf = self.read_raw_page(page_id);
+        let view = NodeView::parse(&buf, self.format_version).unwrap_or_else(|e| panic!("search_node: {}", e));
+
+        match view.binary_search_key(key).unwrap_or_else(|e| panic!("search_node: {}", e)) {
+            Ok(i) => {
+                if view.is_leaf() {
+                    Some(view.value(i).unwrap_or_else(|e| panic!("search_node: {}", e)))
+                } else {
+                    let child = view.child(i + 1).unwrap_or_else(|e| panic!("search_node: {}", e));
+                    self.search_node(child, key)
+                }
+            }
+            Err(i) => {
+                if view.is_leaf() {
+                    None
+                } else {
+                    let child = view.child(i).unwrap_or_else(|e| panic!("search_node: {}", e));
+                    self.search_node(child, key)
+                }
+            }
+        }
+    }
+
+    /// Every `RecordPointer` stored under `key`. `msg` values repeat
+    /// constantly across log lines, so a leaf can hold several entries for
+    /// the same key, but `search` only ever returns the one `binary_search`
+    /// happens to land on -- most matches are otherwise unreachable. Built
+    /// on `range` with equal bounds, since "every entry equal to `key`" is
+    /// just the `[key, key]` range.
+    pub fn search_all(&self, key: &str) -> Vec<RecordPointer> {
+        self.range(key, Some(key)).map(|(_, ptr)| ptr).collect()
+    }
+
+    /// Every `(key, RecordPointer)` pair with a key in `[start, end]`
+    /// (inclusive), or `[start, +inf)` when `end` is `None`, in sorted order.
+    /// Vec-collecting convenience wrapper around `range`, kept for callers
+    /// that want the whole window at once rather than an iterator.
+    pub fn range_scan(&self, start: &str, end: &str) -> Vec<(String, RecordPointer)> {
+        self.range(start, Some(end)).collect()
+    }
+
+    /// Every `(key, RecordPointer)` pair whose key starts with `prefix`.
+    /// Vec-collecting convenience wrapper around `prefix`.
+    pub fn prefix_scan(&self, prefix: &str) -> Vec<(String, RecordPointer)> {
+        self.prefix(prefix).collect()
+    }
+
+    /// Descend to the leftmost leaf that could hold `start`. Unlike
+    /// `search_node` (which only ever needs one match and so descends right
+    /// of an exact separator hit), this has to descend *left* of the first
+    /// separator equal to `start`: a separator is a copy of a leaf key that
+    /// can have earlier duplicate-key entries sitting in the child to its
+    /// left (see `split_child`), and a range/prefix scan needs all of them,
+    /// not just the one the separator points at.
+    fn find_leaf_for(&self, start: &str) -> u64 {
+        let mut page = self.root_page;
+        loop {
+            let node = self.read_node(page);
+            if node.is_leaf {
+                return page;
+            }
+            let idx = node.keys.partition_point(|k| k.as_str() < start);
+            page = node.children[idx];
+        }
+    }
+
+    /// Ordered iteration from `start` through `end` (inclusive), or through
+    /// the last key in the tree when `end` is `None`. Descends once to the
+    /// leftmost leaf whose keys are `>= start`, binary-searches the start
+    /// position within it, then walks `next_leaf` links -- no re-descending
+    /// from the root between leaves, unlike `search`/`search_all` repeated
+    /// per key.
+    pub fn range(&self, start: &str, end: Option<&str>) -> RangeIter<'_> {
+        if self.root_page == 0 {
+            return RangeIter::empty(self);
+        }
+
+        let leaf_page = self.find_leaf_for(start);
+        let node = self.read_node(leaf_page);
+        let pos = node.keys.partition_point(|k| k.as_str() < start);
+
+        let stop: Box<dyn Fn(&str) -> bool> = match end.map(|e| e.to_string()) {
+            Some(end) => Box::new(move |k: &str| k > end.as_str()),
+            None => Box::new(|_: &str| false),
+        };
+
+        RangeIter {
+            tree: self,
+            next_leaf: node.next_leaf,
+            keys: node.keys,
+            values: node.values,
+            pos,
+            stop,
+            done: false,
+        }
+    }
+
+    /// Ordered iteration over every key starting with `prefix`.
+    pub fn prefix(&self, p: &str) -> RangeIter<'_> {
+        if self.root_page == 0 {
+            return RangeIter::empty(self);
+        }
+
+        let leaf_page = self.find_leaf_for(p);
+        let node = self.read_node(leaf_page);
+        let pos = node.keys.partition_point(|k| k.as_str() < p);
+
+        let prefix = p.to_string();
+        let stop: Box<dyn Fn(&str) -> bool> = Box::new(move |k: &str| !k.starts_with(prefix.as_str()));
+
+        RangeIter {
+            tree: self,
+            next_leaf: node.next_leaf,
+            keys: node.keys,
+            values: node.values,
+            pos,
+            stop,
+            done: false,
+        }
+    }
+
+    /// Remove `key` from the tree, rebalancing via sibling borrow or merge so
+    /// every non-root node stays at or above the minimum fill (`ORDER - 1`
+    /// keys). Returns the removed pointer, or `None` if the key was absent.
+    pub fn delete(&mut self, key: &str) -> Option<RecordPointer> {
+        if self.root_page == 0 {
+            return None;
+        }
+
+        let removed = self.delete_node(self.root_page, key);
+
+        let root = self.read_node(self.root_page);
+        if root.is_leaf && root.keys.is_empty() {
+            self.push_free_page(self.root_page);
+            self.root_page = 0;
+            self.update_header();
+        } else if !root.is_leaf && root.keys.is_empty() {
+            let old_root = self.root_page;
+            self.root_page = root.children[0];
+            self.update_header();
+            self.push_free_page(old_root);
+        }
+
+        removed
+    }
+
+    fn delete_node(&mut self, page_id: u64, key: &str) -> Option<RecordPointer> {
         let node = self.read_node(page_id);
+        let key_s = key.to_string();
 
-        match node.keys.binary_search(&key.to_string()) {
+        match node.keys.binary_search(&key_s) {
             Ok(i) => {
                 if node.is_leaf {
-                    return Some(node.values[i]);
+                    let mut node = node;
+                    let ptr = node.values.remove(i);
+                    node.keys.remove(i);
+                    self.write_node(page_id, &node);
+                    Some(ptr)
                 } else {
-                    let child = node.children[i + 1];
-                    return self.search_node(child, key);
+                    self.delete_from_internal(page_id, i)
                 }
             }
             Err(i) => {
                 if node.is_leaf {
-                    return None;
+                    None
                 } else {
-                    let child = node.children[i];
-                    return self.search_node(child, key);
+                    self.delete_from_subtree(page_id, i, &key_s)
                 }
             }
         }
     }
 
+    /// `node.keys[idx]` (an internal separator) is the key being removed.
+    /// Replace it with the in-order predecessor (if the left child can spare
+    /// one) or successor (if the right child can), otherwise merge the two
+    /// children around it and recurse into the merged node.
+    fn delete_from_internal(&mut self, page_id: u64, idx: usize) -> Option<RecordPointer> {
+        let node = self.read_node(page_id);
+        let left_page = node.children[idx];
+        let right_page = node.children[idx + 1];
+        let left = self.read_node(left_page);
+        let right = self.read_node(right_page);
+
+        if left.keys.len() >= ORDER {
+            let (pred_key, pred_val) = self.max_key(left_page);
+            let mut node = node;
+            node.keys[idx] = pred_key.clone();
+            self.write_node(page_id, &node);
+            self.delete_node(left_page, &pred_key);
+            Some(pred_val)
+        } else if right.keys.len() >= ORDER {
+            let (succ_key, succ_val) = self.min_key(right_page);
+            let mut node = node;
+            node.keys[idx] = succ_key.clone();
+            self.write_node(page_id, &node);
+            self.delete_node(right_page, &succ_key);
+            Some(succ_val)
+        } else {
+            let key = node.keys[idx].clone();
+            self.merge_children(page_id, idx);
+            self.delete_node(left_page, &key)
+        }
+    }
+
+    /// Key isn't in `node.keys`; it belongs in `node.children[idx]`. Top off
+    /// that child to at least `ORDER` keys first (borrow or merge), since
+    /// descending into an exactly-minimal child and then removing a key would
+    /// leave it underfull.
+    fn delete_from_subtree(&mut self, page_id: u64, idx: usize, key: &str) -> Option<RecordPointer> {
+        let node = self.read_node(page_id);
+        let child_page = node.children[idx];
+        let child = self.read_node(child_page);
+
+        if child.keys.len() < ORDER {
+            self.fill_child(page_id, idx);
+
+            // The fill may have merged a sibling in, shifting child indices.
+            // Recompute from scratch rather than trying to track the shift.
+            let node = self.read_node(page_id);
+            let new_idx = match node.keys.binary_search(&key.to_string()) {
+                Ok(i) => return self.delete_from_internal(page_id, i),
+                Err(i) => i,
+            };
+            self.delete_node(node.children[new_idx], key)
+        } else {
+            self.delete_node(child_page, key)
+        }
+    }
+
+    /// Ensure `node.children[idx]` has at least `ORDER` keys by borrowing a
+    /// key from a sibling with keys to spare, or merging with one otherwise.
+    fn fill_child(&mut self, page_id: u64, idx: usize) {
+        let node = self.read_node(page_id);
+        let has_left = idx > 0;
+        let has_right = idx + 1 < node.children.len();
+
+        if has_left {
+            let left_sib = self.read_node(node.children[idx - 1]);
+            if left_sib.keys.len() >= ORDER {
+                self.borrow_from_prev(page_id, idx);
+                return;
+            }
+        }
+
+        if has_right {
+            let right_sib = self.read_node(node.children[idx + 1]);
+            if right_sib.keys.len() >= ORDER {
+                self.borrow_from_next(page_id, idx);
+                return;
+            }
+        }
+
+        if has_right {
+            self.merge_children(page_id, idx);
+        } else {
+            self.merge_children(page_id, idx - 1);
+        }
+    }
+
+    /// Move one entry from `children[idx - 1]` (left sibling) into
+    /// `children[idx]` via the parent separator.
+    fn borrow_from_prev(&mut self, page_id: u64, idx: usize) {
+        let mut node = self.read_node(page_id);
+        let child_page = node.children[idx];
+        let sib_page = node.children[idx - 1];
+        let mut child = self.read_node(child_page);
+        let mut sib = self.read_node(sib_page);
+
+        if child.is_leaf {
+            let moved_key = sib.keys.pop().expect("sibling must have a spare key");
+            let moved_val = sib.values.pop().expect("sibling must have a spare value");
+            child.keys.insert(0, moved_key.clone());
+            child.values.insert(0, moved_val);
+            node.keys[idx - 1] = moved_key;
+        } else {
+            let separator = node.keys[idx - 1].clone();
+            let moved_child = sib.children.pop().expect("sibling must have a spare child");
+            let moved_key = sib.keys.pop().expect("sibling must have a spare key");
+            child.keys.insert(0, separator);
+            child.children.insert(0, moved_child);
+            node.keys[idx - 1] = moved_key;
+        }
+
+        self.write_node(child_page, &child);
+        self.write_node(sib_page, &sib);
+        self.write_node(page_id, &node);
+    }
+
+    /// Move one entry from `children[idx + 1]` (right sibling) into
+    /// `children[idx]` via the parent separator.
+    fn borrow_from_next(&mut self, page_id: u64, idx: usize) {
+        let mut node = self.read_node(page_id);
+        let child_page = node.children[idx];
+        let sib_page = node.children[idx + 1];
+        let mut child = self.read_node(child_page);
+        let mut sib = self.read_node(sib_page);
+
+        if child.is_leaf {
+            let moved_key = sib.keys.remove(0);
+            let moved_val = sib.values.remove(0);
+            child.keys.push(moved_key);
+            child.values.push(moved_val);
+            node.keys[idx] = sib.keys[0].clone();
+        } else {
+            let separator = node.keys[idx].clone();
+            let moved_child = sib.children.remove(0);
+            let moved_key = sib.keys.remove(0);
+            child.keys.push(separator);
+            child.children.push(moved_child);
+            node.keys[idx] = moved_key;
+        }
+
+        self.write_node(child_page, &child);
+        self.write_node(sib_page, &sib);
+        self.write_node(page_id, &node);
+    }
+
+    /// Merge `children[idx + 1]` into `children[idx]`, pulling the separator
+    /// key down for internal merges, then free the now-unused right page.
+    fn merge_children(&mut self, page_id: u64, idx: usize) {
+        let mut node = self.read_node(page_id);
+        let left_page = node.children[idx];
+        let right_page = node.children[idx + 1];
+        let mut left = self.read_node(left_page);
+        let right = self.read_node(right_page);
+
+        if left.is_leaf {
+            left.keys.extend(right.keys);
+            left.values.extend(right.values);
+            // `right_page` is about to be freed, so splice it out of the leaf
+            // chain: `left` now points straight to whatever `right` used to.
+            left.next_leaf = right.next_leaf;
+        } else {
+            left.keys.push(node.keys[idx].clone());
+            left.keys.extend(right.keys);
+            left.children.extend(right.children);
+        }
+
+        node.keys.remove(idx);
+        node.children.remove(idx + 1);
+
+        self.write_node(left_page, &left);
+        self.write_node(page_id, &node);
+        self.push_free_page(right_page);
+    }
+
+    fn max_key(&self, page_id: u64) -> (String, RecordPointer) {
+        let node = self.read_node(page_id);
+        if node.is_leaf {
+            let i = node.keys.len() - 1;
+            (node.keys[i].clone(), node.values[i])
+        } else {
+            let last_child = *node.children.last().unwrap();
+            self.max_key(last_child)
+        }
+    }
+
+    fn min_key(&self, page_id: u64) -> (String, RecordPointer) {
+        let node = self.read_node(page_id);
+        if node.is_leaf {
+            (node.keys[0].clone(), node.values[0])
+        } else {
+            self.min_key(node.children[0])
+        }
+    }
+
     pub fn flush(&mut self) {
         self.pool.flush_all().expect("flush_all failed");
         self.pool.sync_all().expect("sync failed");
+        self.pool.refresh_mmap_if_enabled().expect("mmap refresh failed");
+    }
+
+    /// Walk the tree from `root_page` without trusting it, validating structural
+    /// invariants and recording every page that was actually reachable.
+    pub fn check(&self) -> CheckReport {
+        let mut report = CheckReport::default();
+
+        if self.root_page == 0 {
+            return report;
+        }
+
+        let mut visited = HashSet::new();
+        let mut reachable = vec![false; self.next_page as usize];
+        let height = self.check_node(self.root_page, true, &mut visited, &mut reachable, &mut report, 0);
+        report.height = height;
+
+        for page_id in 1..self.next_page {
+            if !reachable[page_id as usize] {
+                report.orphaned_pages.push(page_id);
+            }
+        }
+
+        report
+    }
+
+    fn check_node(
+        &self,
+        page_id: u64,
+        is_root: bool,
+        visited: &mut HashSet<u64>,
+        reachable: &mut Vec<bool>,
+        report: &mut CheckReport,
+        depth: usize,
+    ) -> usize {
+        if page_id >= self.next_page {
+            report.violations.push(Violation {
+                page_id,
+                message: format!("child page {} is >= next_page {}", page_id, self.next_page),
+            });
+            return depth;
+        }
+
+        if !visited.insert(page_id) {
+            report.violations.push(Violation {
+                page_id,
+                message: "page reachable via more than one path (cycle or shared child)".into(),
+            });
+            return depth;
+        }
+
+        reachable[page_id as usize] = true;
+
+        let node = match self.try_read_node(page_id) {
+            Ok(n) => n,
+            Err(BTreeError::CorruptPage { page_id, expected, found }) => {
+                report.violations.push(Violation {
+                    page_id,
+                    message: format!(
+                        "checksum mismatch (expected {:08x}, found {:08x})",
+                        expected, found
+                    ),
+                });
+                return depth;
+            }
+            Err(BTreeError::Io(e)) => {
+                report.violations.push(Violation { page_id, message: format!("io error: {}", e) });
+                return depth;
+            }
+            Err(BTreeError::Malformed(msg)) => {
+                report.violations.push(Violation { page_id, message: format!("malformed node layout: {}", msg) });
+                return depth;
+            }
+            Err(e @ BTreeError::UnrelatedFile { .. }) => {
+                // Never produced by node decoding, only by `BTree::open`, but
+                // the match must stay exhaustive over `BTreeError`.
+                report.violations.push(Violation { page_id, message: format!("{}", e) });
+                return depth;
+            }
+        };
+        report.nodes += 1;
+
+        for w in node.keys.windows(2) {
+            if w[0] > w[1] {
+                report.violations.push(Violation {
+                    page_id,
+                    message: format!("keys not sorted: {:?} > {:?}", w[0], w[1]),
+                });
+            }
+        }
+
+        if node.keys.len() > MAX_KEYS {
+            report.violations.push(Violation {
+                page_id,
+                message: format!("{} keys exceeds MAX_KEYS {}", node.keys.len(), MAX_KEYS),
+            });
+        }
+
+        if !is_root && node.keys.len() < ORDER - 1 {
+            report.violations.push(Violation {
+                page_id,
+                message: format!("{} keys below minimum fill {}", node.keys.len(), ORDER - 1),
+            });
+        }
+
+        if node.is_leaf {
+            report.leaves += 1;
+            report.total_keys += node.keys.len();
+            depth + 1
+        } else {
+            if node.children.len() != node.keys.len() + 1 {
+                report.violations.push(Violation {
+                    page_id,
+                    message: format!(
+                        "internal node has {} children but {} keys",
+                        node.children.len(),
+                        node.keys.len()
+                    ),
+                });
+            }
+
+            let mut max_child_height = depth + 1;
+            for &child in &node.children {
+                let h = self.check_node(child, false, visited, reachable, report, depth + 1);
+                max_child_height = max_child_height.max(h);
+            }
+            max_child_height
+        }
+    }
+
+    /// Collect every `(key, RecordPointer)` pair from the leaves, in sorted order,
+    /// via a plain in-order traversal. Used by `repair` to rebuild a tree from scratch.
+    fn collect_leaf_pairs(&self) -> Vec<(String, RecordPointer)> {
+        let mut out = Vec::new();
+        if self.root_page != 0 {
+            self.collect_leaf_pairs_node(self.root_page, &mut out);
+        }
+        out
+    }
+
+    fn collect_leaf_pairs_node(&self, page_id: u64, out: &mut Vec<(String, RecordPointer)>) {
+        let node = self.read_node(page_id);
+        if node.is_leaf {
+            for (k, v) in node.keys.iter().zip(node.values.iter()) {
+                out.push((k.clone(), *v));
+            }
+        } else {
+            for &child in &node.children {
+                self.collect_leaf_pairs_node(child, out);
+            }
+        }
+    }
+
+    /// Rebuild a fresh, structurally sound tree at `dest_path` from every leaf entry
+    /// reachable from the current root, re-inserted in sorted order. Returns the
+    /// newly built tree; the original file/pages are left untouched.
+    pub fn repair(&self, dest_path: &Path) -> std::io::Result<BTree> {
+        let pairs = self.collect_leaf_pairs();
+        let mut fresh = BTree::open(dest_path);
+        for (key, ptr) in pairs {
+            fresh.insert(key, ptr);
+        }
+        fresh.flush();
+        Ok(fresh)
+    }
+}
+
+/// One structural problem found while walking the tree during `BTree::check`.
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub page_id: u64,
+    pub message: String,
+}
+
+/// Result of `BTree::check`: counts describing the tree plus any violations found.
+#[derive(Debug, Clone, Default)]
+pub struct CheckReport {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub height: usize,
+    pub total_keys: usize,
+    pub violations: Vec<Violation>,
+    pub orphaned_pages: Vec<u64>,
+}
+
+impl CheckReport {
+    pub fn is_clean(&self) -> bool {
+        self.violations.is_empty() && self.orphaned_pages.is_empty()
     }
 }
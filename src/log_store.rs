@@ -0,0 +1,143 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::btree_node::RecordPointer;
+
+/// Default zstd compression level used for every frame. Chosen for fast
+/// writes on a tailing hot path rather than maximum ratio.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Encoded size of one frame-table entry: an 8-byte compressed offset plus
+/// two 4-byte lengths.
+const FRAME_ENTRY_SIZE: usize = 16;
+
+/// Where one frame's compressed bytes live in the `.zst` data file, plus its
+/// uncompressed length so `fetch_record` can bounds-check before slicing.
+#[derive(Clone, Copy)]
+struct FrameEntry {
+    compressed_offset: u64,
+    compressed_len: u32,
+    uncompressed_len: u32,
+}
+
+/// An append-only, compressed, seekable archive of tailed log lines, modeled
+/// on pijul's use of `zstd_seekable`: content is written as a sequence of
+/// independently decompressible zstd frames rather than one compressed
+/// stream, so `fetch_record` only has to decompress the one frame holding a
+/// given record. A small frame-offset table (frame id -> compressed byte
+/// offset/length) is kept in a sibling `.idx` file and loaded into memory in
+/// full at `open`, the same way `BTree` keeps its header page in memory --
+/// it's one entry per tailed batch, not per record, so it stays tiny.
+pub struct CompressedLogStore {
+    data_file: File,
+    index_file: File,
+    frames: Vec<FrameEntry>,
+}
+
+impl CompressedLogStore {
+    /// Open (creating if absent) the `.zst` data file at `path` and its
+    /// sibling `<path>.idx` frame-offset table.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let data_file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+
+        let index_path = Self::index_path(path);
+        let mut index_file = OpenOptions::new().create(true).read(true).append(true).open(&index_path)?;
+
+        let mut raw = Vec::new();
+        index_file.seek(SeekFrom::Start(0))?;
+        index_file.read_to_end(&mut raw)?;
+
+        let frames = raw
+            .chunks_exact(FRAME_ENTRY_SIZE)
+            .map(|chunk| FrameEntry {
+                compressed_offset: u64::from_le_bytes(chunk[0..8].try_into().unwrap()),
+                compressed_len: u32::from_le_bytes(chunk[8..12].try_into().unwrap()),
+                uncompressed_len: u32::from_le_bytes(chunk[12..16].try_into().unwrap()),
+            })
+            .collect();
+
+        Ok(Self { data_file, index_file, frames })
+    }
+
+    fn index_path(path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(".idx");
+        PathBuf::from(name)
+    }
+
+    /// Number of frames written so far, i.e. the id the next `write_frame`
+    /// call will return.
+    pub fn frame_count(&self) -> u64 {
+        self.frames.len() as u64
+    }
+
+    /// Compress `content` as one new frame, append it to the data file, and
+    /// record its entry in the frame-offset table. Returns the new frame's
+    /// id, which callers stash in `RecordPointer::frame_id` alongside the
+    /// record's offset within `content`.
+    pub fn write_frame(&mut self, content: &[u8]) -> io::Result<u64> {
+        let compressed = zstd::encode_all(content, ZSTD_LEVEL)?;
+
+        let compressed_offset = self.data_file.seek(SeekFrom::End(0))?;
+        self.data_file.write_all(&compressed)?;
+
+        let entry = FrameEntry {
+            compressed_offset,
+            compressed_len: compressed.len() as u32,
+            uncompressed_len: content.len() as u32,
+        };
+
+        let mut record = [0u8; FRAME_ENTRY_SIZE];
+        record[0..8].copy_from_slice(&entry.compressed_offset.to_le_bytes());
+        record[8..12].copy_from_slice(&entry.compressed_len.to_le_bytes());
+        record[12..16].copy_from_slice(&entry.uncompressed_len.to_le_bytes());
+        self.index_file.write_all(&record)?;
+
+        let frame_id = self.frames.len() as u64;
+        self.frames.push(entry);
+        Ok(frame_id)
+    }
+
+    /// Decompress the single frame holding `ptr` and slice out its record.
+    /// `ptr.offset`/`ptr.length` are interpreted as a byte range within that
+    /// frame's decompressed content, not a position in any host log file.
+    pub fn fetch_record(&mut self, ptr: RecordPointer) -> io::Result<String> {
+        let frame_id = ptr
+            .frame_id
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "pointer has no frame id: record lives in a host log file, not this store"))?;
+
+        let entry = *self
+            .frames
+            .get(frame_id as usize)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("frame {} not found", frame_id)))?;
+
+        let mut compressed = vec![0u8; entry.compressed_len as usize];
+        self.data_file.seek(SeekFrom::Start(entry.compressed_offset))?;
+        self.data_file.read_exact(&mut compressed)?;
+
+        let decompressed = zstd::decode_all(&compressed[..])?;
+        if decompressed.len() != entry.uncompressed_len as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "frame {} decompressed to {} bytes, expected {}",
+                    frame_id,
+                    decompressed.len(),
+                    entry.uncompressed_len
+                ),
+            ));
+        }
+
+        let start = ptr.offset as usize;
+        let end = start + ptr.length as usize;
+        if end > decompressed.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("record range {}..{} exceeds frame {} content ({} bytes)", start, end, frame_id, decompressed.len()),
+            ));
+        }
+
+        Ok(String::from_utf8_lossy(&decompressed[start..end]).into_owned())
+    }
+}
@@ -2,8 +2,8 @@ mod btree_node;
 
 use crate::btree_node::{BTree, RecordPointer};
 use serde::Serialize;
-use std::fs::{OpenOptions, create_dir_all, File};
-use std::io::{Write, Seek, SeekFrom, Read};
+use std::fs::{OpenOptions, create_dir_all};
+use std::io::{Write, Seek, SeekFrom};
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 use btree_node::{DISK_READS, DISK_WRITES};
@@ -67,6 +67,8 @@ fn main() {
         let ptr = RecordPointer {
             offset,
             length: json_line.len() as u32,
+            frame_id: None,
+            file_id: 0,
         };
 
         // Insert into B-tree index
@@ -84,7 +86,7 @@ fn main() {
         println!("FOUND '{}' at offset={} length={}",
             search_key, ptr.offset, ptr.length);
 
-        let actual = read_log_entry(ptr);
+        let actual = btree.read_log_entry(ptr, Path::new(LOG_PATH)).unwrap();
         println!("\nActual log line:\n{}", actual);
     } else {
         println!("Not found: {}", search_key);
@@ -101,15 +103,3 @@ fn main() {
     println!("Disk Writes : {}", writes);
     println!("----------------------------------");
 }
-
-// Read log entry from log file using RecordPointer
-pub fn read_log_entry(ptr: RecordPointer) -> String {
-    let mut file = File::open(LOG_PATH).unwrap();
-
-    file.seek(SeekFrom::Start(ptr.offset)).unwrap();
-
-    let mut buf = vec![0u8; ptr.length as usize];
-    file.read_exact(&mut buf).unwrap();
-
-    String::from_utf8(buf).unwrap()
-}
@@ -1,19 +1,22 @@
 mod btree_node;
 
-use crate::btree_node::{BTree, RecordPointer};
-use std::fs::File;
-use std::io::{self, Write, Read, Seek, SeekFrom};
+use crate::btree_node::BTree;
+use std::io::{self, Write};
 use std::path::Path;
 
+/// Fallback log file for `file_id: 0` pointers when no segment is registered
+/// under 0 (in particular every pointer predating segments, which are all
+/// `file_id: 0`). Pointers with any other unregistered `file_id` fail to resolve.
+const DEFAULT_LOG_PATH: &str = "/data/logs/app.log";
+
 fn main() {
     println!("Rust Log Search Tool (LOCAL MODE)");
     println!("----------------------------------");
     println!("Index file: /data/index.data");
-    println!("WARNING: This search reads from a SINGLE log file only.");
     println!("Type a key to search, or 'exit' to quit.\n");
 
     // Open B-tree index
-    let mut btree = BTree::open(Path::new("/data/index.data"));
+    let btree = BTree::open(Path::new("/data/index.data"));
 
     loop {
         print!("search> ");
@@ -35,7 +38,9 @@ fn main() {
                     key, ptr.offset, ptr.length
                 );
 
-                let actual = read_log_entry(ptr);
+                let actual = btree
+                    .read_log_entry(ptr, Path::new(DEFAULT_LOG_PATH))
+                    .unwrap_or_else(|e| format!("<failed to read log entry: {}>", e));
                 println!("Log Entry:\n{}\n", actual);
             }
             None => {
@@ -44,20 +49,3 @@ fn main() {
         }
     }
 }
-
-pub fn read_log_entry(ptr: RecordPointer) -> String {
-    // CHANGE THIS PATH TO YOUR LOG FILE IF NEEDED
-    let log_path = "/data/logs/app.log";
-
-    let mut file = File::open(log_path)
-        .unwrap_or_else(|_| panic!("Cannot open local log file: {}", log_path));
-
-    file.seek(SeekFrom::Start(ptr.offset))
-        .expect("seek failed");
-
-    let mut buf = vec![0u8; ptr.length as usize];
-    file.read_exact(&mut buf)
-        .expect("failed to read log bytes");
-
-    String::from_utf8_lossy(&buf).into_owned()
-}
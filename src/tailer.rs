@@ -4,15 +4,45 @@ use std::io::{Read, Seek, SeekFrom};
 use std::path::PathBuf;
 
 use crate::btree_node::{BTree, RecordPointer};
+use crate::log_store::CompressedLogStore;
 use serde_json::Value;
 
 pub struct LogTailer {
     offsets: HashMap<PathBuf, u64>,
+    /// When set, newly tailed lines are compressed into this store instead
+    /// of being pointed at by raw offsets into the host log file.
+    store: Option<CompressedLogStore>,
+    /// `file_id` assigned to each tailed path's segment, registered with the
+    /// `BTree` the first time that path is tailed.
+    file_ids: HashMap<PathBuf, u32>,
+    /// Next `file_id` to hand out. Starts at 1: 0 stays the implicit segment
+    /// every pointer meant before segments existed.
+    next_file_id: u32,
 }
 
 impl LogTailer {
     pub fn new() -> Self {
-        Self { offsets: HashMap::new() }
+        Self { offsets: HashMap::new(), store: None, file_ids: HashMap::new(), next_file_id: 1 }
+    }
+
+    /// Like `new`, but every batch of newly tailed lines is compressed into
+    /// `store` as one zstd frame instead of left pointing at raw bytes in
+    /// the (mutable, rotating) host log file.
+    pub fn with_compressed_store(store: CompressedLogStore) -> Self {
+        Self { offsets: HashMap::new(), store: Some(store), file_ids: HashMap::new(), next_file_id: 1 }
+    }
+
+    /// Segment id this path is (or will be) registered under, assigning and
+    /// persisting a fresh one to `btree` the first time it's seen.
+    fn file_id_for(&mut self, path: &PathBuf, btree: &mut BTree) -> u32 {
+        if let Some(&id) = self.file_ids.get(path) {
+            return id;
+        }
+        let id = self.next_file_id;
+        self.next_file_id += 1;
+        btree.register_segment(id, path.clone()).expect("register_segment failed");
+        self.file_ids.insert(path.clone(), id);
+        id
     }
 
     pub fn tail_file(&mut self, path: &PathBuf, btree: &mut BTree) {
@@ -39,6 +69,12 @@ impl LogTailer {
             return;
         }
 
+        // When a compressed store is in use, this whole batch of newly
+        // tailed bytes becomes one zstd frame, so a lookup for any record in
+        // it only has to decompress that one frame rather than the batch
+        // stream as a whole.
+        let frame_id = self.store.as_mut().map(|store| store.write_frame(&buf).expect("write_frame failed"));
+
         // Process new logs
         let mut pos_in_buf: usize = 0;
 
@@ -54,8 +90,6 @@ impl LogTailer {
                 Err(_) => String::from_utf8_lossy(line_bytes).into_owned(),
             };
 
-            // Compute absolute file offset
-            let line_offset = old_offset + pos_in_buf as u64;
             let line_len = line_bytes.len();
 
             // -------------------------
@@ -69,10 +103,15 @@ impl LogTailer {
                 Err(_) => line_str.clone(),
             };
 
-            // Build pointer
-            let ptr = RecordPointer {
-                offset: line_offset,
-                length: line_len as u32,
+            // Build pointer: a frame-relative range when compressing (file_id
+            // is meaningless there, the record lives in the compressed store
+            // instead), or the absolute offset into this path's segment when not.
+            let ptr = match frame_id {
+                Some(fid) => RecordPointer { offset: pos_in_buf as u64, length: line_len as u32, frame_id: Some(fid), file_id: 0 },
+                None => {
+                    let file_id = self.file_id_for(path, btree);
+                    RecordPointer { offset: old_offset + pos_in_buf as u64, length: line_len as u32, frame_id: None, file_id }
+                }
             };
 
             // Insert into B-tree
@@ -1,8 +1,10 @@
 use std::fs;
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::Path;
 
-use disk_btree::btree_node::{BTree, RecordPointer, PAGE_SIZE};
+use std::sync::atomic::Ordering;
+
+use disk_btree::btree_node::{BTree, BTreeNode, RecordPointer, CHECKSUM_SIZE, DISK_READS, MAX_KEYS, MMAP_HITS, PAGE_SIZE};
 
 #[test]
 fn insert_test() {
@@ -14,7 +16,7 @@ fn insert_test() {
 
     let mut bt = BTree::open(path);
 
-    bt.insert("akash".to_string(), RecordPointer { offset: 10, length: 5 });
+    bt.insert("akash".to_string(), RecordPointer { offset: 10, length: 5, frame_id: None, file_id: 0 });
 
     let root = bt.read_node(bt.root_page);
 
@@ -41,9 +43,9 @@ fn header_consistency_test() {
         assert_eq!(bt.root_page, 0);
         assert_eq!(bt.next_page, 1);
 
-        bt.insert("a".into(), RecordPointer { offset: 10, length: 5 });
-        bt.insert("b".into(), RecordPointer { offset: 20, length: 5 });
-        bt.insert("c".into(), RecordPointer { offset: 30, length: 5 });
+        bt.insert("a".into(), RecordPointer { offset: 10, length: 5, frame_id: None, file_id: 0 });
+        bt.insert("b".into(), RecordPointer { offset: 20, length: 5, frame_id: None, file_id: 0 });
+        bt.insert("c".into(), RecordPointer { offset: 30, length: 5, frame_id: None, file_id: 0 });
 
         assert!(bt.root_page > 0);
     }
@@ -54,8 +56,8 @@ fn header_consistency_test() {
     file.seek(SeekFrom::Start(0)).unwrap();
     file.read_exact(&mut header).unwrap();
 
-    let root_from_disk = u64::from_le_bytes(header[0..8].try_into().unwrap());
-    let next_from_disk = u64::from_le_bytes(header[8..16].try_into().unwrap());
+    let root_from_disk = u64::from_le_bytes(header[CHECKSUM_SIZE..CHECKSUM_SIZE + 8].try_into().unwrap());
+    let next_from_disk = u64::from_le_bytes(header[CHECKSUM_SIZE + 8..CHECKSUM_SIZE + 16].try_into().unwrap());
 
     let mut bt2 = BTree::open(path);
 
@@ -99,7 +101,7 @@ fn insert_into_empty() {
     assert_eq!(btree.root_page, 0);
     assert_eq!(btree.next_page, 1);
 
-    btree.insert("A".to_string(), RecordPointer { offset: 111, length: 10 });
+    btree.insert("A".to_string(), RecordPointer { offset: 111, length: 10, frame_id: None, file_id: 0 });
 
     assert!(btree.root_page > 0);
 
@@ -124,7 +126,7 @@ fn inset_more_key(){
     let totolkey=200;
     for i in 0..totolkey{
         let key = format!("k{}",i);
-         btree.insert(key.clone(), RecordPointer { offset: i as u64, length: 4 });
+         btree.insert(key.clone(), RecordPointer { offset: i as u64, length: 4, frame_id: None, file_id: 0 });
     }
     assert!(btree.next_page>=20);
     assert!(btree.root_page>0);
@@ -141,5 +143,386 @@ fn inset_more_key(){
     }
 
 
+}
+
+#[test]
+fn delete_reclaims_pages_via_free_list() {
+    let path = Path::new("delete_test.idx");
+
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+
+    let mut btree = BTree::open(path);
+    let total_keys = 300;
+    for i in 0..total_keys {
+        let key = format!("d{:04}", i);
+        btree.insert(key, RecordPointer { offset: i as u64, length: 4, frame_id: None, file_id: 0 });
+    }
+
+    for i in 0..total_keys {
+        let key = format!("d{:04}", i);
+        let expected = btree.search(&key);
+        let removed = btree.delete(&key);
+        assert_eq!(removed.map(|p| p.offset), expected.map(|p| p.offset), "delete({}) should match prior search", key);
+        assert!(btree.search(&key).is_none(), "key {} must be gone after delete", key);
+    }
+
+    assert_eq!(btree.root_page, 0, "tree should be empty once every key is deleted");
+    assert!(btree.free_list_head != 0, "freed pages should be tracked on the free list");
+
+    // alloc_page must reuse a freed page instead of growing the file further.
+    let next_page_before = btree.next_page;
+    let reused = btree.alloc_page();
+    assert!(reused < next_page_before, "alloc_page should have popped a freed page");
+}
+
+#[test]
+fn range_and_prefix_walk_linked_leaves_in_order() {
+    let path = Path::new("range_prefix_test.idx");
+
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+
+    let mut btree = BTree::open(path);
+    let total_keys = 300;
+    for i in 0..total_keys {
+        let key = format!("r{:04}", i);
+        btree.insert(key, RecordPointer { offset: i as u64, length: 4, frame_id: None, file_id: 0 });
+    }
+
+    // Splitting this many keys forces several leaf splits, so this also
+    // exercises next_leaf threading, not just a single-leaf scan.
+    let scanned: Vec<(String, RecordPointer)> = btree.range("r0010", Some("r0020")).collect();
+    let expected_keys: Vec<String> = (10..=20).map(|i| format!("r{:04}", i)).collect();
+    assert_eq!(scanned.iter().map(|(k, _)| k.clone()).collect::<Vec<_>>(), expected_keys);
+
+    let open_ended: Vec<(String, RecordPointer)> = btree.range("r0298", None).collect();
+    assert_eq!(open_ended.len(), 2);
+
+    let all: Vec<(String, RecordPointer)> = btree.range("r0000", None).collect();
+    assert_eq!(all.len(), total_keys);
+    assert!(all.windows(2).all(|w| w[0].0 < w[1].0), "range must yield keys in sorted order");
+}
+
+#[test]
+fn range_scan_stays_consistent_across_merge_and_borrow_deletes() {
+    let path = Path::new("range_after_delete_test.idx");
+
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+
+    let mut btree = BTree::open(path);
+    let total_keys = 300;
+    for i in 0..total_keys {
+        let key = format!("m{:04}", i);
+        btree.insert(key, RecordPointer { offset: i as u64, length: 4, frame_id: None, file_id: 0 });
+    }
+
+    // Delete every other key so survivors are spread across leaves that get
+    // merged and borrowed from, then confirm the leaf chain still walks the
+    // remaining keys in order with none skipped or duplicated.
+    for i in (0..total_keys).step_by(2) {
+        let key = format!("m{:04}", i);
+        assert!(btree.delete(&key).is_some(), "key {} should have been present", key);
+    }
+
+    let remaining: Vec<String> = btree.range("m0000", None).map(|(k, _)| k).collect();
+    let expected: Vec<String> = (0..total_keys).filter(|i| i % 2 == 1).map(|i| format!("m{:04}", i)).collect();
+    assert_eq!(remaining, expected);
+}
+
+#[test]
+fn repeated_search_hits_page_cache_without_extra_disk_reads() {
+    let path = Path::new("cache_hit_test.idx");
+
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+
+    let mut btree = BTree::open_with_capacity(path, 1024).unwrap();
+    let total_keys = 300;
+    for i in 0..total_keys {
+        let key = format!("c{:04}", i);
+        btree.insert(key, RecordPointer { offset: i as u64, length: 4, frame_id: None, file_id: 0 });
+    }
+    btree.flush();
+
+    let key = "c0150";
+
+    // First lookup may miss the cache on every page along its root-to-leaf
+    // path. Prime it, then measure the delta for a repeat lookup of the
+    // same key: every page it touches should now be cache-resident.
+    assert!(btree.search(key).is_some());
+
+    let reads_before = DISK_READS.load(Ordering::Relaxed);
+    assert!(btree.search(key).is_some());
+    let reads_after = DISK_READS.load(Ordering::Relaxed);
+
+    assert_eq!(reads_after, reads_before, "a repeat search of the same key must not touch disk again");
+}
+
+#[test]
+fn flipped_byte_in_data_page_trips_checksum_on_read() {
+    let path = Path::new("corrupt_page_test.idx");
+
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+
+    {
+        let mut btree = BTree::open(path);
+        btree.insert("a".into(), RecordPointer { offset: 1, length: 1, frame_id: None, file_id: 0 });
+        btree.flush();
+    }
+
+    // Page 0 is the header; page 1 is the first data page the root leaf
+    // landed on. Flip a byte inside its content, past the checksum prefix.
+    {
+        let mut file = fs::OpenOptions::new().read(true).write(true).open(path).unwrap();
+        file.seek(SeekFrom::Start(PAGE_SIZE as u64 + CHECKSUM_SIZE as u64 + 3)).unwrap();
+        let mut byte = [0u8; 1];
+        file.seek(SeekFrom::Start(PAGE_SIZE as u64 + CHECKSUM_SIZE as u64 + 3)).unwrap();
+        file.read_exact(&mut byte).unwrap();
+        byte[0] ^= 0xFF;
+        file.seek(SeekFrom::Start(PAGE_SIZE as u64 + CHECKSUM_SIZE as u64 + 3)).unwrap();
+        file.write_all(&byte).unwrap();
+    }
+
+    let btree = BTree::open(path);
+    let err = btree.try_read_node(btree.root_page).expect_err("corrupted page must fail its checksum");
+    assert!(matches!(err, disk_btree::btree_node::BTreeError::CorruptPage { page_id: 1, .. }));
+}
+
+/// Mirrors the private `crc32` in `btree_node.rs`, just enough to build a
+/// header page with a checksum that passes so this test exercises the
+/// magic/version check specifically, not the checksum check.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[test]
+fn open_rejects_a_file_with_the_wrong_magic() {
+    let path = Path::new("bad_magic_test.idx");
+
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+
+    // A single, checksum-valid header page for a file this build doesn't
+    // recognize: non-zero root/next so it isn't treated as a blank header,
+    // and a magic that is neither `HEADER_MAGIC` nor the legacy all-zero stamp.
+    let mut header = [0u8; PAGE_SIZE];
+    header[CHECKSUM_SIZE..CHECKSUM_SIZE + 8].copy_from_slice(&5u64.to_le_bytes());
+    header[CHECKSUM_SIZE + 8..CHECKSUM_SIZE + 16].copy_from_slice(&6u64.to_le_bytes());
+    header[CHECKSUM_SIZE + 16..CHECKSUM_SIZE + 24].copy_from_slice(&0u64.to_le_bytes());
+    header[CHECKSUM_SIZE + 24..CHECKSUM_SIZE + 28].copy_from_slice(b"XXXX");
+    header[CHECKSUM_SIZE + 28] = 9;
+    let sum = crc32(&header[CHECKSUM_SIZE..]);
+    header[0..CHECKSUM_SIZE].copy_from_slice(&sum.to_le_bytes());
+    fs::write(path, header).unwrap();
+
+    let result = BTree::open_with_capacity(path, 16);
+    assert!(result.is_err(), "a file with an unrecognized magic must be rejected, not misread as a tree");
+}
+
+#[test]
+fn prefix_scan_matches_only_prefixed_keys() {
+    let path = Path::new("prefix_scan_test.idx");
+
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+
+    let mut btree = BTree::open(path);
+    for i in 0..50 {
+        btree.insert(format!("svc-a/{:03}", i), RecordPointer { offset: i as u64, length: 4, frame_id: None, file_id: 0 });
+    }
+    for i in 0..50 {
+        btree.insert(format!("svc-b/{:03}", i), RecordPointer { offset: 1000 + i as u64, length: 4, frame_id: None, file_id: 0 });
+    }
+
+    let matches = btree.prefix_scan("svc-a/");
+    assert_eq!(matches.len(), 50);
+    assert!(matches.iter().all(|(k, _)| k.starts_with("svc-a/")));
+}
+
+#[test]
+fn search_all_finds_every_duplicate_across_a_split() {
+    let path = Path::new("duplicate_key_test.idx");
+
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+
+    let mut btree = BTree::open(path);
+
+    // One key inserted enough times to force several leaf splits: every
+    // duplicate's RecordPointer must still come back, including the ones
+    // left behind in a leaf to the left of the separator that eventually
+    // gets promoted for this key (see split_child / find_leaf_for).
+    let total = 400;
+    for i in 0..total {
+        btree.insert("msg:disk full".to_string(), RecordPointer { offset: i as u64, length: 4, frame_id: None, file_id: 0 });
+    }
+    assert!(btree.root_page > 0, "enough duplicates must force the tree to grow past a single leaf");
+
+    let mut all = btree.search_all("msg:disk full");
+    all.sort_by_key(|p| p.offset);
+    assert_eq!(all.len(), total, "search_all must return every duplicate, not just the one the separator points at");
+    for (i, ptr) in all.iter().enumerate() {
+        assert_eq!(ptr.offset, i as u64);
+    }
+
+    let ranged: Vec<_> = btree.range("msg:disk full", Some("msg:disk full")).collect();
+    assert_eq!(ranged.len(), total, "range over [key, key] must also return every duplicate");
+
+    // A shared prefix across distinct keys must likewise surface every
+    // duplicate under each of them once the tree has split.
+    for i in 0..total {
+        btree.insert("msg:disk warn".to_string(), RecordPointer { offset: 10_000 + i as u64, length: 4, frame_id: None, file_id: 0 });
+    }
+    let prefixed = btree.prefix_scan("msg:disk ");
+    assert_eq!(prefixed.len(), 2 * total, "prefix_scan must return every duplicate under every matching key");
+}
+#[test]
+fn registered_segments_resolve_and_legacy_file_id_zero_falls_back() {
+    let path = Path::new("segments_test.idx");
+
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+    let default_log = Path::new("segments_test_default.log");
+    let rolled_log = Path::new("segments_test_rolled.log");
+    fs::write(default_log, b"from the default log\n").unwrap();
+    fs::write(rolled_log, b"from the rolled segment\n").unwrap();
+
+    let mut btree = BTree::open(path);
+    btree.register_segment(2, rolled_log.to_path_buf()).unwrap();
+
+    let legacy_ptr = RecordPointer { offset: 0, length: 21, frame_id: None, file_id: 0 };
+    let rolled_ptr = RecordPointer { offset: 0, length: 24, frame_id: None, file_id: 2 };
+
+    assert_eq!(btree.read_log_entry(legacy_ptr, default_log).unwrap(), "from the default log\n");
+    assert_eq!(btree.read_log_entry(rolled_ptr, default_log).unwrap(), "from the rolled segment\n");
+    assert!(btree.read_log_entry(RecordPointer { offset: 0, length: 1, frame_id: None, file_id: 7 }, default_log).is_err());
+
+    drop(btree);
+
+    // The registry must survive a close/reopen: it lives in the header page.
+    let reopened = BTree::open(path);
+    assert_eq!(reopened.segment_path(2), Some(rolled_log));
+
+    fs::remove_file(default_log).ok();
+    fs::remove_file(rolled_log).ok();
+}
+
+#[test]
+fn varint_node_round_trips_byte_for_byte_near_capacity() {
+    let path = Path::new("varint_roundtrip_test.idx");
+
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+
+    let mut btree = BTree::open(path);
+
+    // A leaf packed right up to MAX_KEYS, with varied-length keys and
+    // RecordPointer fields so small and large varints both get exercised.
+    let mut leaf = BTreeNode::new_leaf();
+    for i in 0..MAX_KEYS {
+        leaf.keys.push(format!("rt-key-{:04}", i));
+        leaf.values.push(RecordPointer {
+            offset: (i as u64) * 123_456_789,
+            length: (i as u32) * 7,
+            frame_id: if i % 2 == 0 { None } else { Some(i as u64 * 99) },
+            file_id: i as u32,
+        });
+    }
+
+    let page_a = btree.alloc_page();
+    let page_b = btree.alloc_page();
+
+    btree.write_node(page_a, &leaf);
+    let decoded = btree.read_node(page_a);
+
+    assert_eq!(decoded.keys, leaf.keys);
+    assert_eq!(decoded.values.len(), leaf.values.len());
+    for (got, want) in decoded.values.iter().zip(leaf.values.iter()) {
+        assert_eq!(got.offset, want.offset);
+        assert_eq!(got.length, want.length);
+        assert_eq!(got.frame_id, want.frame_id);
+        assert_eq!(got.file_id, want.file_id);
+    }
+
+    // Byte-for-byte: re-encoding the decoded node must reproduce exactly the
+    // same page bytes the original encode produced.
+    btree.write_node(page_b, &decoded);
+    let bytes_a = btree.pool.read_page_copy(page_a).unwrap();
+    let bytes_b = btree.pool.read_page_copy(page_b).unwrap();
+    assert_eq!(bytes_a, bytes_b, "decoding then re-encoding a near-capacity node must round-trip byte-for-byte");
+}
+
+#[test]
+fn memmap_reads_serve_from_the_mapping_not_the_disk_path() {
+    let path = Path::new("memmap_test.idx");
+
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
 
-}
\ No newline at end of file
+    {
+        let mut btree = BTree::open(path);
+        for i in 0..300 {
+            let key = format!("mm{:04}", i);
+            btree.insert(key, RecordPointer { offset: i as u64, length: 4, frame_id: None, file_id: 0 });
+        }
+        btree.flush();
+    }
+
+    let btree = BTree::memmap(path).unwrap();
+
+    let hits_before = MMAP_HITS.load(Ordering::Relaxed);
+    let reads_before = DISK_READS.load(Ordering::Relaxed);
+
+    assert_eq!(btree.search("mm0150").unwrap().offset, 150);
+
+    assert!(MMAP_HITS.load(Ordering::Relaxed) > hits_before, "search through a mapped tree must record mmap hits");
+    assert_eq!(DISK_READS.load(Ordering::Relaxed), reads_before, "mmap hits must bypass the disk-read counter entirely");
+}
+
+#[test]
+fn insert_after_memmap_is_visible_without_an_explicit_flush() {
+    let path = Path::new("memmap_write_through_test.idx");
+
+    if path.exists() {
+        fs::remove_file(path).unwrap();
+    }
+
+    {
+        let mut btree = BTree::open(path);
+        btree.insert("existing".into(), RecordPointer { offset: 1, length: 1, frame_id: None, file_id: 0 });
+        btree.flush();
+    }
+
+    let mut btree = BTree::memmap(path).unwrap();
+
+    // Inserting into an already-mapped tree dirties the page in the cache
+    // without remapping; a read for the same key must see the cache, not a
+    // stale copy out of the mapping taken before the insert.
+    btree.insert("fresh".into(), RecordPointer { offset: 99, length: 2, frame_id: None, file_id: 0 });
+
+    let found = btree.search("fresh");
+    assert!(found.is_some(), "a key inserted after memmap must be findable without a flush first");
+    assert_eq!(found.unwrap().offset, 99);
+}